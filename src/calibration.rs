@@ -0,0 +1,179 @@
+use crate::{
+    geometry::{DOT_NAMES, DOTS, Dot, NUM_DOTS, Zone},
+    mixer::Mixer,
+    true_gear_message,
+    websocket::TrueGearWebsocketClient,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, error::Error, io::Write, path::Path};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+// length of the test pulse played on each dot
+const TEST_PULSE_MS: u16 = 600;
+
+// rating of a dot that feels as strong as expected
+const REFERENCE_RATING: f32 = 3.0;
+const MAX_RATING: u8 = 5;
+
+// Per-dot gain file, keyed by dot name, e.g. "TrueGearA1"
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct CalibrationFile {
+    pub gains: BTreeMap<String, f32>,
+}
+
+// Per-dot gains compensating for fit and motor variance
+#[derive(Debug, Clone, Copy)]
+pub struct Calibration {
+    pub gains: [f32; NUM_DOTS],
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            gains: [1.0; NUM_DOTS],
+        }
+    }
+}
+
+impl Calibration {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read calibration file {}: {}", path.display(), e))?;
+        let file: CalibrationFile = serde_json::from_str(&text)
+            .map_err(|e| format!("failed to parse calibration file {}: {}", path.display(), e))?;
+
+        let mut calibration = Self::default();
+        for (name, gain) in file.gains {
+            let Some(dot) = Dot::from_name(&name) else {
+                return Err(format!("unknown dot '{}' in calibration file", name).into());
+            };
+            if !gain.is_finite() || gain < 0.0 {
+                return Err(format!("invalid gain {} for dot '{}'", gain, name).into());
+            }
+            calibration.gains[dot.compact_index()] = gain;
+        }
+
+        Ok(calibration)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let file = CalibrationFile {
+            gains: DOT_NAMES
+                .iter()
+                .zip(self.gains)
+                .map(|(name, gain)| (name.to_string(), gain))
+                .collect(),
+        };
+        let text = serde_json::to_string_pretty(&file)?;
+        std::fs::write(path, text)
+            .map_err(|e| format!("failed to write calibration file {}: {}", path.display(), e))?;
+        Ok(())
+    }
+}
+
+fn describe(dot: Dot) -> String {
+    match dot.zone() {
+        Zone::Front => format!("front, row {}, column {}", dot.row() + 1, dot.column() + 1),
+        Zone::Back => format!("back, row {}, column {}", dot.row() + 1, dot.column() + 1),
+        Zone::LeftArm => "left arm".to_string(),
+        Zone::RightArm => "right arm".to_string(),
+    }
+}
+
+fn build_test_effect(dot: Dot, intensity: u16, interval: u8) -> true_gear_message::Effect {
+    let mut track = Mixer::build_track(dot.channel(), intensity, intensity, interval, &[dot]);
+    track.end_time = TEST_PULSE_MS;
+
+    true_gear_message::Effect {
+        uuid: "VRChatCalibrate".to_string(),
+        name: "VRChatCalibrate".to_string(),
+        keep: false,
+        priority: 0,
+        tracks: vec![track],
+    }
+}
+
+// Walk every dot through the vest, ask for a rating of its strength on the terminal,
+// and write the resulting per-dot gains
+pub async fn run_wizard(
+    url: String,
+    shake_intensity: u16,
+    electrical_intensity: u16,
+    electrical_interval: u8,
+    output: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut client = TrueGearWebsocketClient::new(url);
+    client.start().await?;
+
+    println!("Each dot plays in turn. Rate how strong it feels:");
+    println!(
+        "  1-{}  strength, {} = as expected",
+        MAX_RATING, REFERENCE_RATING
+    );
+    println!("  r    replay the dot");
+    println!("  x    not felt, or felt in the wrong place");
+    println!("  q    quit without saving");
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut calibration = Calibration::default();
+    let mut misplaced = Vec::new();
+
+    for (i, dot) in DOTS.into_iter().enumerate() {
+        let (intensity, interval) = match dot.zone() {
+            Zone::LeftArm | Zone::RightArm => (electrical_intensity, electrical_interval),
+            Zone::Front | Zone::Back => (shake_intensity, 0),
+        };
+        let effect = build_test_effect(dot, intensity, interval);
+
+        loop {
+            client.send_play_effect(&effect).await?;
+
+            print!(
+                "[{}/{}] {} ({}): ",
+                i + 1,
+                NUM_DOTS,
+                dot.name(),
+                describe(dot)
+            );
+            std::io::stdout().flush()?;
+
+            let Some(line) = lines.next_line().await? else {
+                return Err("calibration aborted, no input".into());
+            };
+
+            match line.trim() {
+                "r" | "" => continue,
+                "q" => {
+                    println!("Calibration aborted, nothing saved");
+                    return Ok(());
+                }
+                "x" => {
+                    misplaced.push(dot.name());
+                    break;
+                }
+                rating => match rating.parse::<u8>() {
+                    Ok(rating) if (1..=MAX_RATING).contains(&rating) => {
+                        // weak dots are boosted, strong dots are attenuated
+                        calibration.gains[i] = REFERENCE_RATING / rating as f32;
+                        break;
+                    }
+                    _ => println!("Enter 1-{}, r, x or q", MAX_RATING),
+                },
+            }
+        }
+    }
+
+    calibration.save(output)?;
+    println!("Saved calibration to {}", output.display());
+
+    if misplaced.is_empty() {
+        println!("All dots fired in the expected place");
+    } else {
+        println!(
+            "Check the fit of the vest, these dots were not felt in the expected place: {}",
+            misplaced.join(", ")
+        );
+    }
+
+    Ok(())
+}
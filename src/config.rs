@@ -0,0 +1,223 @@
+use crate::{
+    control::{ControlBinding, GestureCommand},
+    curve::ResponseCurve,
+    dot_mapping::{Channel, DotGroup},
+    effect_library::EffectTrigger,
+    encoding::EncodedInput,
+    heart_rate::HeartRateConfig,
+    mapping::Layout,
+    mixer::BlendMode,
+    smoothing::{ImpactBoost, Smoothing},
+    spatial::{DirectionConfig, PointConfig},
+    vrchat::VrchatConfig,
+};
+use serde::Deserialize;
+use std::{error::Error, path::Path};
+
+// Per-channel tuning of the dot feedback
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ChannelConfig {
+    // time to ramp a dot from zero to full intensity
+    pub attack_ms: u64,
+    // time to ramp a dot from full intensity down to zero once its input is released
+    pub release_ms: u64,
+    // response from the input level to the output level
+    pub curve: ResponseCurve,
+    // output level of the weakest non-zero input, as a fraction of the channel intensity
+    pub floor: f32,
+    // output level of the strongest input, as a fraction of the channel intensity
+    pub ceiling: f32,
+    // a dot switches on once its input rises above this value
+    pub activation_threshold: f32,
+    // an active dot switches off once its input drops to this value, defaults to the activation
+    // threshold
+    pub deactivation_threshold: Option<f32>,
+    // minimum time a dot stays on once switched on
+    pub min_on_ms: u64,
+    // low-pass filter of the input of active dots
+    pub smoothing: Smoothing,
+    // extra intensity on sudden rising edges
+    pub impact_boost: Option<ImpactBoost>,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self {
+            attack_ms: 0,
+            release_ms: 150,
+            curve: ResponseCurve::default(),
+            floor: 0.0,
+            ceiling: 1.0,
+            activation_threshold: 0.0,
+            deactivation_threshold: None,
+            min_on_ms: 0,
+            smoothing: Smoothing::default(),
+            impact_boost: None,
+        }
+    }
+}
+
+impl ChannelConfig {
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        self.curve.validate()?;
+        self.smoothing.validate()?;
+        if !(0.0..=1.0).contains(&self.floor) || !(0.0..=1.0).contains(&self.ceiling) {
+            return Err("floor and ceiling must be within 0.0 - 1.0".into());
+        }
+        if self.floor > self.ceiling {
+            return Err("floor must not be above ceiling".into());
+        }
+        if self.off_threshold() > self.activation_threshold {
+            return Err("deactivation_threshold must not be above activation_threshold".into());
+        }
+        Ok(())
+    }
+
+    // input value at or below which an active dot switches off
+    pub fn off_threshold(&self) -> f32 {
+        self.deactivation_threshold
+            .unwrap_or(self.activation_threshold)
+    }
+
+    // Map an input level to an output level, zero stays zero
+    pub fn shape(&self, level: f32) -> f32 {
+        if level <= 0.0 {
+            return 0.0;
+        }
+        self.floor + self.curve.apply(level) * (self.ceiling - self.floor)
+    }
+}
+
+// Tuning of the edge-triggered "Once" feedback mode
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OnceConfig {
+    // length of the pulse fired on each rising edge
+    pub pulse_ms: u64,
+    // a dot fires when its input rises above this value, and re-arms once it drops back to it
+    pub rearm_threshold: f32,
+}
+
+impl Default for OnceConfig {
+    fn default() -> Self {
+        Self {
+            pulse_ms: 150,
+            rearm_threshold: 0.0,
+        }
+    }
+}
+
+// Drives both arms from one electrical input, distributed by a pan input
+#[derive(Debug, Clone, Deserialize)]
+pub struct ArmPanConfig {
+    // electrical intensity input
+    pub address: String,
+    // pan input, 0.0 for the left arm only, 0.5 for both arms, 1.0 for the right arm only
+    pub pan_address: String,
+}
+
+// How a source of feedback is mixed with the others
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LayerConfig {
+    pub blend: BlendMode,
+    // priority of the layer; library effects default to their own priority, others to 0
+    pub priority: Option<u16>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MixerConfig {
+    // continuous per-dot feedback
+    pub contact: LayerConfig,
+    // effects from the effect library
+    pub effects: LayerConfig,
+    // procedural pattern generators and the heart rate heartbeat
+    pub generators: LayerConfig,
+    // reactions to VRChat's built-in avatar parameters
+    pub reactions: LayerConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub shake: ChannelConfig,
+    pub electrical: ChannelConfig,
+    pub once: OnceConfig,
+    // switch off dots that received no input for this long (0 to disable)
+    pub stale_timeout_ms: u64,
+    // input addresses bound to runtime settings
+    pub control_bindings: Vec<ControlBinding>,
+    // hand gesture combinations firing pause / resume / emergency stop commands
+    pub gesture_commands: Vec<GestureCommand>,
+    // input addresses playing effects from the effect library
+    pub effect_triggers: Vec<EffectTrigger>,
+    // default minimum time between two plays of the same trigger
+    pub effect_cooldown_ms: u64,
+    pub mixer: MixerConfig,
+    // transform of the dots driven by the inputs
+    pub layout: Layout,
+    pub arm_pan: Option<ArmPanConfig>,
+    // compact input encodings for synced avatar parameters
+    pub encoded_inputs: Vec<EncodedInput>,
+    // named groups of dots driven by a single input
+    pub groups: Vec<DotGroup>,
+    // spread of /truegear/point inputs
+    pub point: PointConfig,
+    // spread of /truegear/direction inputs
+    pub direction: DirectionConfig,
+    pub heart_rate: Option<HeartRateConfig>,
+    // reactions to VRChat's built-in avatar parameters
+    pub vrchat: VrchatConfig,
+}
+
+impl Config {
+    pub fn channel(&self, channel: Channel) -> &ChannelConfig {
+        match channel {
+            Channel::Shake => &self.shake,
+            Channel::Electrical => &self.electrical,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read config file {}: {}", path.display(), e))?;
+        let config: Config = serde_json::from_str(&text)
+            .map_err(|e| format!("failed to parse config file {}: {}", path.display(), e))?;
+        for (name, channel) in [("shake", &config.shake), ("electrical", &config.electrical)] {
+            channel
+                .validate()
+                .map_err(|e| format!("invalid {} config in {}: {}", name, path.display(), e))?;
+        }
+        for encoded_input in &config.encoded_inputs {
+            encoded_input
+                .validate()
+                .map_err(|e| format!("invalid encoded input in {}: {}", path.display(), e))?;
+        }
+        for group in &config.groups {
+            group
+                .validate()
+                .map_err(|e| format!("invalid group in {}: {}", path.display(), e))?;
+        }
+        config
+            .point
+            .falloff
+            .validate()
+            .map_err(|e| format!("invalid point falloff in {}: {}", path.display(), e))?;
+        config
+            .direction
+            .validate()
+            .map_err(|e| format!("invalid direction spread in {}: {}", path.display(), e))?;
+        if let Some(heart_rate) = &config.heart_rate {
+            heart_rate
+                .validate()
+                .map_err(|e| format!("invalid heart rate in {}: {}", path.display(), e))?;
+        }
+        config
+            .vrchat
+            .validate()
+            .map_err(|e| format!("invalid vrchat reactions in {}: {}", path.display(), e))?;
+        Ok(config)
+    }
+}
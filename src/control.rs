@@ -0,0 +1,233 @@
+use crate::{geometry::NUM_ELECTRICAL, mapping::FeedbackMode};
+use clap::ValueEnum;
+use rosc::{OscMessage, OscType};
+use serde::Deserialize;
+
+// Namespace of the runtime control addresses, e.g. /truegear/config/shake_intensity
+const CONTROL_ROOT: &str = "/truegear/config/";
+
+const GESTURE_LEFT_ADDRESS: &str = "/avatar/parameters/GestureLeft";
+const GESTURE_RIGHT_ADDRESS: &str = "/avatar/parameters/GestureRight";
+
+// highest intensity accepted by TrueGear
+pub const MAX_INTENSITY: u16 = 150;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlSetting {
+    ShakeIntensity,
+    ElectricalIntensity,
+    ElectricalInterval,
+    LeftArmIntensity,
+    RightArmIntensity,
+    LeftArmInterval,
+    RightArmInterval,
+    FeedbackMode,
+}
+
+impl ControlSetting {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "shake_intensity" => Some(Self::ShakeIntensity),
+            "electrical_intensity" => Some(Self::ElectricalIntensity),
+            "electrical_interval" => Some(Self::ElectricalInterval),
+            "left_arm_intensity" => Some(Self::LeftArmIntensity),
+            "right_arm_intensity" => Some(Self::RightArmIntensity),
+            "left_arm_interval" => Some(Self::LeftArmInterval),
+            "right_arm_interval" => Some(Self::RightArmInterval),
+            "feedback_mode" => Some(Self::FeedbackMode),
+            _ => None,
+        }
+    }
+
+    // arms affected by an electrical setting, left then right
+    fn arms(&self) -> &'static [usize] {
+        match self {
+            Self::LeftArmIntensity | Self::LeftArmInterval => &[0],
+            Self::RightArmIntensity | Self::RightArmInterval => &[1],
+            _ => &[0, 1],
+        }
+    }
+}
+
+// Binds an input address, e.g. an avatar expression-menu parameter, to a runtime setting
+#[derive(Debug, Clone, Deserialize)]
+pub struct ControlBinding {
+    pub address: String,
+    pub setting: ControlSetting,
+}
+
+// Settings that can be changed while running
+#[derive(Debug, Clone)]
+pub struct RuntimeSettings {
+    pub shake_intensity: u16,
+    // per arm, left then right
+    pub electrical_intensity: [u16; NUM_ELECTRICAL],
+    pub electrical_interval: [u8; NUM_ELECTRICAL],
+    pub feedback_mode: FeedbackMode,
+}
+
+impl RuntimeSettings {
+    // Settings addressed by a control message, either in the control namespace or through a binding
+    pub fn match_settings(addr: &str, bindings: &[ControlBinding]) -> Vec<ControlSetting> {
+        let mut settings: Vec<ControlSetting> = bindings
+            .iter()
+            .filter(|b| b.address == addr)
+            .map(|b| b.setting)
+            .collect();
+
+        if let Some(setting) = addr
+            .strip_prefix(CONTROL_ROOT)
+            .and_then(ControlSetting::from_name)
+        {
+            settings.push(setting);
+        }
+
+        settings
+    }
+
+    // Apply a control message to a setting.
+    // Integer values are taken as-is, normalized values (Float, Double, Bool) span the full range.
+    pub fn apply(&mut self, setting: ControlSetting, msg: &OscMessage) -> bool {
+        let Some(arg) = msg.args.first() else {
+            return false;
+        };
+
+        match setting {
+            ControlSetting::ShakeIntensity => {
+                let Some(v) = Self::extract_scaled(arg, MAX_INTENSITY as f64) else {
+                    return false;
+                };
+                self.shake_intensity = v.clamp(0, MAX_INTENSITY as i64) as u16;
+                tracing::info!("Shake intensity set to {}", self.shake_intensity);
+            }
+            ControlSetting::ElectricalIntensity
+            | ControlSetting::LeftArmIntensity
+            | ControlSetting::RightArmIntensity => {
+                let Some(v) = Self::extract_scaled(arg, MAX_INTENSITY as f64) else {
+                    return false;
+                };
+                for &arm in setting.arms() {
+                    self.electrical_intensity[arm] = v.clamp(0, MAX_INTENSITY as i64) as u16;
+                }
+                tracing::info!(
+                    "Electrical intensity set to {:?} (left, right)",
+                    self.electrical_intensity
+                );
+            }
+            ControlSetting::ElectricalInterval
+            | ControlSetting::LeftArmInterval
+            | ControlSetting::RightArmInterval => {
+                let Some(v) = Self::extract_scaled(arg, u8::MAX as f64) else {
+                    return false;
+                };
+                for &arm in setting.arms() {
+                    self.electrical_interval[arm] = v.clamp(0, u8::MAX as i64) as u8;
+                }
+                tracing::info!(
+                    "Electrical interval set to {:?} (left, right)",
+                    self.electrical_interval
+                );
+            }
+            ControlSetting::FeedbackMode => {
+                let mode = match arg {
+                    OscType::String(s) => FeedbackMode::from_str(s.trim(), true).ok(),
+                    OscType::Bool(b) => Some(if *b {
+                        FeedbackMode::Once
+                    } else {
+                        FeedbackMode::Continuous
+                    }),
+                    OscType::Int(i) => Some(if *i != 0 {
+                        FeedbackMode::Once
+                    } else {
+                        FeedbackMode::Continuous
+                    }),
+                    _ => None,
+                };
+                let Some(mode) = mode else {
+                    return false;
+                };
+                self.feedback_mode = mode;
+                tracing::info!("Feedback mode set to {:?}", self.feedback_mode);
+            }
+        }
+
+        true
+    }
+
+    fn extract_scaled(arg: &OscType, full_scale: f64) -> Option<i64> {
+        match arg {
+            OscType::Int(i) => Some(*i as i64),
+            OscType::Long(l) => Some(*l),
+            OscType::Float(f) => Some((*f as f64 * full_scale).round() as i64),
+            OscType::Double(f) => Some((*f * full_scale).round() as i64),
+            OscType::Bool(b) => Some(if *b { full_scale as i64 } else { 0 }),
+            OscType::String(s) => s.trim().parse::<i64>().ok(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Command {
+    Pause,
+    Resume,
+    EmergencyStop,
+    Rearm,
+}
+
+impl Command {
+    pub fn from_address(addr: &str) -> Option<Self> {
+        match addr {
+            "/truegear/pause" => Some(Self::Pause),
+            "/truegear/resume" => Some(Self::Resume),
+            "/truegear/stop" => Some(Self::EmergencyStop),
+            "/truegear/rearm" => Some(Self::Rearm),
+            _ => None,
+        }
+    }
+
+    // A command fires on a message without arguments, or with a true / non-zero first argument,
+    // so that buttons sending true then false only fire once
+    pub fn is_triggered(msg: &OscMessage) -> bool {
+        match msg.args.first() {
+            None => true,
+            Some(OscType::Bool(b)) => *b,
+            Some(OscType::Int(i)) => *i != 0,
+            Some(OscType::Long(l)) => *l != 0,
+            Some(OscType::Float(f)) => *f > 0.0,
+            Some(OscType::Double(f)) => *f > 0.0,
+            Some(_) => true,
+        }
+    }
+}
+
+// Fires a command when the avatar makes a combination of hand gestures
+#[derive(Debug, Clone, Deserialize)]
+pub struct GestureCommand {
+    // VRChat GestureLeft / GestureRight values, e.g. 1 for a fist
+    pub left: i32,
+    pub right: i32,
+    pub command: Command,
+}
+
+impl GestureCommand {
+    // Update the current [left, right] gestures from a message, returns whether it was a gesture
+    pub fn update_gestures(gestures: &mut [i32; 2], msg: &OscMessage) -> bool {
+        let side = match msg.addr.as_str() {
+            GESTURE_LEFT_ADDRESS => 0,
+            GESTURE_RIGHT_ADDRESS => 1,
+            _ => return false,
+        };
+        let Some(OscType::Int(gesture)) = msg.args.first() else {
+            return false;
+        };
+        gestures[side] = *gesture;
+        true
+    }
+
+    pub fn matches(&self, gestures: &[i32; 2]) -> bool {
+        gestures[0] == self.left && gestures[1] == self.right
+    }
+}
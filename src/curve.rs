@@ -0,0 +1,155 @@
+use serde::Deserialize;
+use std::error::Error;
+
+// Shape of the response from a normalized input level to a normalized output level
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseCurve {
+    #[default]
+    Linear,
+    // level ^ gamma, values above 1 soften light contacts
+    Gamma {
+        gamma: f32,
+    },
+    // exponential rise, larger k gives a steeper end of the range
+    Exponential {
+        k: f32,
+    },
+    // logistic curve around the middle of the range, larger steepness gives a sharper step
+    SCurve {
+        steepness: f32,
+    },
+    // piecewise-linear lookup table of [input, output] points, sorted by input
+    Lut {
+        points: Vec<[f32; 2]>,
+    },
+}
+
+impl ResponseCurve {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        match self {
+            ResponseCurve::Linear => {}
+            ResponseCurve::Gamma { gamma } => {
+                if *gamma <= 0.0 {
+                    return Err(format!("gamma must be positive, got {}", gamma).into());
+                }
+            }
+            ResponseCurve::Exponential { .. } => {}
+            ResponseCurve::SCurve { steepness } => {
+                if *steepness <= 0.0 {
+                    return Err(format!("steepness must be positive, got {}", steepness).into());
+                }
+            }
+            ResponseCurve::Lut { points } => {
+                if points.len() < 2 {
+                    return Err("lookup table needs at least 2 points".into());
+                }
+                if points.windows(2).any(|w| w[0][0] >= w[1][0]) {
+                    return Err("lookup table inputs must be strictly increasing".into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Map a level in 0.0 - 1.0 through the curve
+    pub fn apply(&self, level: f32) -> f32 {
+        let level = level.clamp(0.0, 1.0);
+        match self {
+            ResponseCurve::Linear => level,
+            ResponseCurve::Gamma { gamma } => level.powf(*gamma),
+            ResponseCurve::Exponential { k } => {
+                if k.abs() < f32::EPSILON {
+                    level
+                } else {
+                    (k * level).exp_m1() / k.exp_m1()
+                }
+            }
+            ResponseCurve::SCurve { steepness } => {
+                let logistic = |x: f32| 1.0 / (1.0 + (-steepness * (x - 0.5)).exp());
+                let (low, high) = (logistic(0.0), logistic(1.0));
+                (logistic(level) - low) / (high - low)
+            }
+            ResponseCurve::Lut { points } => {
+                let first = points[0];
+                let last = points[points.len() - 1];
+                if level <= first[0] {
+                    return first[1];
+                }
+                if level >= last[0] {
+                    return last[1];
+                }
+                let segment = points
+                    .windows(2)
+                    .find(|w| level <= w[1][0])
+                    .expect("level is within the table");
+                let ([x0, y0], [x1, y1]) = (segment[0], segment[1]);
+                y0 + (y1 - y0) * (level - x0) / (x1 - x0)
+            }
+        }
+        .clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn curves_keep_the_ends_of_the_range() {
+        let curves = [
+            ResponseCurve::Linear,
+            ResponseCurve::Gamma { gamma: 2.0 },
+            ResponseCurve::Exponential { k: 3.0 },
+            ResponseCurve::Exponential { k: 0.0 },
+            ResponseCurve::SCurve { steepness: 10.0 },
+        ];
+        for curve in curves {
+            assert_close(curve.apply(0.0), 0.0);
+            assert_close(curve.apply(1.0), 1.0);
+            // inputs are clamped
+            assert_close(curve.apply(-1.0), 0.0);
+            assert_close(curve.apply(2.0), 1.0);
+        }
+    }
+
+    #[test]
+    fn curves_shape_the_middle_of_the_range() {
+        assert_close(ResponseCurve::Linear.apply(0.3), 0.3);
+        assert_close(ResponseCurve::Gamma { gamma: 2.0 }.apply(0.5), 0.25);
+        assert!(ResponseCurve::Exponential { k: 3.0 }.apply(0.5) < 0.5);
+        assert_close(ResponseCurve::Exponential { k: 0.0 }.apply(0.4), 0.4);
+        assert_close(ResponseCurve::SCurve { steepness: 10.0 }.apply(0.5), 0.5);
+        assert!(ResponseCurve::SCurve { steepness: 10.0 }.apply(0.25) < 0.25);
+    }
+
+    #[test]
+    fn lut_interpolates_between_points() {
+        let curve = ResponseCurve::Lut {
+            points: vec![[0.2, 0.1], [0.6, 0.5], [1.0, 0.8]],
+        };
+        assert!(curve.validate().is_ok());
+        assert_close(curve.apply(0.0), 0.1);
+        assert_close(curve.apply(0.4), 0.3);
+        assert_close(curve.apply(0.8), 0.65);
+        assert_close(curve.apply(1.0), 0.8);
+    }
+
+    #[test]
+    fn invalid_curves_are_rejected() {
+        assert!(ResponseCurve::Gamma { gamma: 0.0 }.validate().is_err());
+        assert!(
+            ResponseCurve::SCurve { steepness: -1.0 }
+                .validate()
+                .is_err()
+        );
+        let unsorted = ResponseCurve::Lut {
+            points: vec![[0.5, 0.0], [0.5, 1.0]],
+        };
+        assert!(unsorted.validate().is_err());
+    }
+}
@@ -0,0 +1,360 @@
+use crate::{
+    geometry::{DOT_NAMES, DOTS, Dot, Zone, parse_dot_names},
+    true_gear_message,
+};
+use rosc::address::{Matcher, OscAddress};
+use serde::Deserialize;
+use std::{collections::HashMap, error::Error, path::Path};
+
+// characters reserved for OSC address patterns
+const PATTERN_CHARS: &[char] = &['*', '?', '[', ']', '{', '}'];
+
+const HIERARCHICAL_ROOT: &str = "/truegear";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    Shake,
+    Electrical,
+}
+
+impl From<&true_gear_message::ActionType> for Channel {
+    fn from(action_type: &true_gear_message::ActionType) -> Self {
+        match action_type {
+            true_gear_message::ActionType::Shake => Channel::Shake,
+            true_gear_message::ActionType::Electrical => Channel::Electrical,
+        }
+    }
+}
+
+impl From<Channel> for true_gear_message::ActionType {
+    fn from(channel: Channel) -> Self {
+        match channel {
+            Channel::Shake => true_gear_message::ActionType::Shake,
+            Channel::Electrical => true_gear_message::ActionType::Electrical,
+        }
+    }
+}
+
+fn default_gain() -> f32 {
+    1.0
+}
+
+// A single entry of the mapping file
+#[derive(Debug, Clone, Deserialize)]
+pub struct MappingEntry {
+    // OSC address of the input parameter, e.g. /avatar/parameters/TrueGearA1;
+    // may be an OSC address pattern, e.g. /avatar/parameters/Chest*
+    pub address: String,
+    // channel of the target dots
+    pub channel: Channel,
+    // target dot IDs in TrueGear's definition
+    pub dots: Vec<u8>,
+    // multiplier applied to the input value
+    #[serde(default = "default_gain")]
+    pub gain: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MappingFile {
+    pub mappings: Vec<MappingEntry>,
+}
+
+// A named group of dots, driven as a whole by /avatar/parameters/<name> or /truegear/group/<name>
+#[derive(Debug, Clone, Deserialize)]
+pub struct DotGroup {
+    pub name: String,
+    // member dot names, e.g. TrueGearA1
+    #[serde(default)]
+    pub dots: Vec<String>,
+    // member zones, e.g. front
+    #[serde(default)]
+    pub zones: Vec<Zone>,
+}
+
+impl DotGroup {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        parse_dot_names(&self.dots).map_err(|e| format!("{} in group '{}'", e, self.name))?;
+        Ok(())
+    }
+
+    pub fn addresses(&self) -> [String; 2] {
+        [
+            format!("/avatar/parameters/{}", self.name),
+            format!("{}/group/{}", HIERARCHICAL_ROOT, self.name),
+        ]
+    }
+
+    // compact indices of the member dots, names are checked when loading
+    pub fn members(&self) -> Vec<usize> {
+        let mut members: Vec<usize> = parse_dot_names(&self.dots)
+            .unwrap_or_default()
+            .into_iter()
+            .chain(
+                self.zones
+                    .iter()
+                    .flat_map(|zone| zone.dots().map(|dot| dot.compact_index())),
+            )
+            .collect();
+        members.sort_unstable();
+        members.dedup();
+        members
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DotTarget {
+    pub compact_index: usize,
+    pub gain: f32,
+}
+
+struct MappedAddress {
+    address: OscAddress,
+    targets: Vec<DotTarget>,
+}
+
+struct MappedPattern {
+    matcher: Matcher,
+    targets: Vec<DotTarget>,
+}
+
+// Lookup table from input addresses to the dots they drive
+#[derive(Default)]
+pub struct DotMapping {
+    addresses: HashMap<String, MappedAddress>,
+    patterns: Vec<MappedPattern>,
+}
+
+impl DotMapping {
+    // The built-in mapping for the TrueGear VRChat avatar parameters
+    pub fn builtin() -> Self {
+        let entries: Vec<MappingEntry> = DOT_NAMES
+            .iter()
+            .zip(DOTS)
+            .map(|(name, dot)| MappingEntry {
+                address: format!("/avatar/parameters/{}", name),
+                channel: dot.channel(),
+                dots: vec![dot.wire_id()],
+                gain: default_gain(),
+            })
+            .collect();
+
+        Self::from_entries(&entries).expect("built-in mapping must be valid")
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read mapping file {}: {}", path.display(), e))?;
+        let file: MappingFile = serde_json::from_str(&text)
+            .map_err(|e| format!("failed to parse mapping file {}: {}", path.display(), e))?;
+        Self::from_entries(&file.mappings)
+    }
+
+    // Addresses of the hierarchical scheme, independent of avatar parameter names:
+    // /truegear/{front,back}/<row>/<column> for shake dots, /truegear/arm/{left,right} for electrical dots
+    fn hierarchical_entries() -> Vec<MappingEntry> {
+        DOTS.iter()
+            .map(|dot| {
+                let address = match dot.zone() {
+                    Zone::Front | Zone::Back => format!(
+                        "{}/{}/{}/{}",
+                        HIERARCHICAL_ROOT,
+                        if dot.zone() == Zone::Front {
+                            "front"
+                        } else {
+                            "back"
+                        },
+                        dot.row() + 1,
+                        dot.column() + 1
+                    ),
+                    Zone::LeftArm => format!("{}/arm/left", HIERARCHICAL_ROOT),
+                    Zone::RightArm => format!("{}/arm/right", HIERARCHICAL_ROOT),
+                };
+                MappingEntry {
+                    address,
+                    channel: dot.channel(),
+                    dots: vec![dot.wire_id()],
+                    gain: default_gain(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn from_entries(entries: &[MappingEntry]) -> Result<Self, Box<dyn Error>> {
+        let mut mapping = Self::default();
+
+        // the hierarchical scheme is always available, user entries may extend it
+        for entry in Self::hierarchical_entries().iter().chain(entries) {
+            let targets = Self::resolve_targets(entry)?;
+
+            if entry.address.contains(PATTERN_CHARS) {
+                let matcher = Matcher::new(&entry.address).map_err(|e| {
+                    format!("invalid mapping address pattern '{}': {}", entry.address, e)
+                })?;
+                mapping.patterns.push(MappedPattern { matcher, targets });
+            } else {
+                let address = OscAddress::new(entry.address.clone())
+                    .map_err(|e| format!("invalid mapping address '{}': {}", entry.address, e))?;
+                mapping
+                    .addresses
+                    .entry(entry.address.clone())
+                    .or_insert_with(|| MappedAddress {
+                        address,
+                        targets: Vec::new(),
+                    })
+                    .targets
+                    .extend(targets);
+            }
+        }
+
+        Ok(mapping)
+    }
+
+    fn resolve_targets(entry: &MappingEntry) -> Result<Vec<DotTarget>, Box<dyn Error>> {
+        entry
+            .dots
+            .iter()
+            .map(|&dot| {
+                let Some(dot) = Dot::from_wire_id(entry.channel, dot) else {
+                    return Err(format!(
+                        "invalid {:?} dot ID {} for mapping address '{}'",
+                        entry.channel, dot, entry.address
+                    )
+                    .into());
+                };
+                Ok(DotTarget {
+                    compact_index: dot.compact_index(),
+                    gain: entry.gain,
+                })
+            })
+            .collect()
+    }
+
+    // Find the mapped addresses matching an incoming OSC address.
+    // The incoming address may itself be an OSC address pattern, in which case it is dispatched
+    // to every mapped address it matches, as described in OSC 1.0.
+    pub fn lookup<'a>(&'a self, addr: &str) -> Vec<(&'a str, &'a [DotTarget])> {
+        let mut matched = Vec::new();
+
+        if addr.contains(PATTERN_CHARS) {
+            let Ok(matcher) = Matcher::new(addr) else {
+                return matched;
+            };
+            for (key, mapped) in &self.addresses {
+                if matcher.match_address(&mapped.address) {
+                    matched.push((key.as_str(), mapped.targets.as_slice()));
+                }
+            }
+            return matched;
+        }
+
+        if let Some((key, mapped)) = self.addresses.get_key_value(addr) {
+            matched.push((key.as_str(), mapped.targets.as_slice()));
+        }
+
+        if !self.patterns.is_empty() {
+            let Ok(address) = OscAddress::new(addr.to_string()) else {
+                return matched;
+            };
+            for mapped in &self.patterns {
+                if mapped.matcher.match_address(&address) {
+                    matched.push((mapped.matcher.pattern.as_str(), mapped.targets.as_slice()));
+                }
+            }
+        }
+
+        matched
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len() + self.patterns.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(targets: &[DotTarget]) -> Vec<usize> {
+        targets.iter().map(|t| t.compact_index).collect()
+    }
+
+    #[test]
+    fn lookup_exact_address() {
+        let mapping = DotMapping::builtin();
+        let matched = mapping.lookup("/avatar/parameters/TrueGearB1");
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].0, "/avatar/parameters/TrueGearB1");
+        assert_eq!(indices(matched[0].1), [5]);
+
+        // front row 1, column 1 is B1
+        let matched = mapping.lookup("/truegear/front/1/1");
+        assert_eq!(indices(matched[0].1), [5]);
+
+        assert!(mapping.lookup("/avatar/parameters/Unknown").is_empty());
+    }
+
+    #[test]
+    fn lookup_incoming_pattern() {
+        let mapping = DotMapping::builtin();
+        let mut matched: Vec<_> = mapping
+            .lookup("/avatar/parameters/TrueGearA?")
+            .into_iter()
+            .flat_map(|(_, targets)| indices(targets))
+            .collect();
+        matched.sort_unstable();
+        assert_eq!(matched, [0, 1, 2, 3, 4]);
+
+        assert_eq!(mapping.lookup("/truegear/arm/*").len(), 2);
+    }
+
+    #[test]
+    fn lookup_mapped_pattern() {
+        let entries = [MappingEntry {
+            address: "/avatar/parameters/Chest*".to_string(),
+            channel: Channel::Shake,
+            dots: vec![0, 1],
+            gain: 0.5,
+        }];
+        let mapping = DotMapping::from_entries(&entries).unwrap();
+
+        let matched = mapping.lookup("/avatar/parameters/ChestLeft");
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].0, "/avatar/parameters/Chest*");
+        // wire IDs 0 and 1 are B1 and A1
+        assert_eq!(indices(matched[0].1), [5, 0]);
+        assert_eq!(matched[0].1[0].gain, 0.5);
+
+        assert!(mapping.lookup("/avatar/parameters/Back").is_empty());
+    }
+
+    #[test]
+    fn invalid_dot_ids_are_rejected() {
+        let entries = [MappingEntry {
+            address: "/avatar/parameters/Arm".to_string(),
+            channel: Channel::Electrical,
+            dots: vec![1],
+            gain: 1.0,
+        }];
+        assert!(DotMapping::from_entries(&entries).is_err());
+    }
+
+    #[test]
+    fn group_members() {
+        let group = DotGroup {
+            name: "Arms".to_string(),
+            dots: vec!["TrueGearArmL".to_string(), "TrueGearA1".to_string()],
+            zones: vec![Zone::LeftArm, Zone::RightArm],
+        };
+        assert!(group.validate().is_ok());
+        // sorted, without duplicates
+        assert_eq!(group.members(), [0, 40, 41]);
+
+        let group = DotGroup {
+            name: "Typo".to_string(),
+            dots: vec!["TrueGearArmLeft".to_string()],
+            zones: Vec::new(),
+        };
+        assert!(group.validate().is_err());
+    }
+}
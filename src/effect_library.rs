@@ -0,0 +1,214 @@
+use crate::{
+    dot_mapping::Channel,
+    geometry::{Dot, NUM_DOTS, NUM_SHAKES},
+    mixer::{self, Frame},
+    true_gear_message,
+};
+use serde::Deserialize;
+use std::{collections::BTreeMap, error::Error, path::Path};
+use tokio::time::Instant;
+
+// Avatar parameters named with this prefix play the library effect of the same name,
+// e.g. /avatar/parameters/TrueGearFx_Hit plays Hit.json
+const AVATAR_TRIGGER_PREFIX: &str = "/avatar/parameters/TrueGearFx_";
+
+// Plays an effect by name (String) or by index (Int)
+const PLAY_ADDRESS: &str = "/truegear/play";
+
+// Stops a playing effect by name (String)
+pub const STOP_ADDRESS: &str = "/truegear/stop_effect";
+
+// Binds an input address to a library effect
+#[derive(Debug, Clone, Deserialize)]
+pub struct EffectTrigger {
+    pub address: String,
+    // effect to play; if omitted, Int values select the effect by index
+    pub effect: Option<String>,
+    // minimum time between two plays of this trigger, defaults to effect_cooldown_ms
+    pub cooldown_ms: Option<u64>,
+}
+
+// Effects loaded from a directory of TrueGear effect JSON files, keyed by file name
+#[derive(Debug, Clone, Default)]
+pub struct EffectLibrary {
+    effects: BTreeMap<String, true_gear_message::Effect>,
+}
+
+impl EffectLibrary {
+    pub fn load(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut effects = BTreeMap::new();
+
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| format!("failed to read effect directory {}: {}", dir.display(), e))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+
+            let text = std::fs::read_to_string(&path)
+                .map_err(|e| format!("failed to read effect {}: {}", path.display(), e))?;
+            let effect: true_gear_message::Effect = serde_json::from_str(&text)
+                .map_err(|e| format!("failed to parse effect {}: {}", path.display(), e))?;
+            Self::validate(&effect)
+                .map_err(|e| format!("invalid effect {}: {}", path.display(), e))?;
+            effects.insert(name.to_string(), effect);
+        }
+
+        Ok(Self { effects })
+    }
+
+    // Effects are sent to TrueGear as-is, so reject dot IDs it does not define
+    fn validate(effect: &true_gear_message::Effect) -> Result<(), Box<dyn Error>> {
+        for track in &effect.tracks {
+            let channel = Channel::from(&track.action_type);
+            for &id in &track.index {
+                if Dot::from_wire_id(channel, id).is_none() {
+                    return Err(format!("invalid {:?} dot ID {}", channel, id).into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<(&str, &true_gear_message::Effect)> {
+        self.effects
+            .get_key_value(name)
+            .map(|(name, effect)| (name.as_str(), effect))
+    }
+
+    // Effects are indexed by name order, starting at 1; 0 selects nothing
+    pub fn get_by_index(&self, index: i64) -> Option<(&str, &true_gear_message::Effect)> {
+        if index < 1 {
+            return None;
+        }
+        self.effects
+            .iter()
+            .nth((index - 1) as usize)
+            .map(|(name, effect)| (name.as_str(), effect))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.effects.keys().map(|name| name.as_str())
+    }
+
+    // Whether an address can trigger library effects without being configured
+    pub fn is_builtin_trigger(addr: &str) -> bool {
+        addr == PLAY_ADDRESS || addr == STOP_ADDRESS || addr.starts_with(AVATAR_TRIGGER_PREFIX)
+    }
+
+    // Name of the effect implied by a built-in trigger address, if any
+    pub fn builtin_trigger_effect(addr: &str) -> Option<&str> {
+        addr.strip_prefix(AVATAR_TRIGGER_PREFIX)
+    }
+}
+
+// A library effect being played, rendered tick by tick into mixer frames
+#[derive(Debug, Clone)]
+pub struct PlayingEffect {
+    pub name: String,
+    effect: true_gear_message::Effect,
+    started: Instant,
+}
+
+impl PlayingEffect {
+    pub fn new(name: String, effect: true_gear_message::Effect, started: Instant) -> Self {
+        Self {
+            name,
+            effect,
+            started,
+        }
+    }
+
+    pub fn priority(&self) -> u16 {
+        self.effect.priority
+    }
+
+    fn duration_ms(&self) -> u32 {
+        self.effect
+            .tracks
+            .iter()
+            .map(|t| t.end_time as u32)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn elapsed_ms(&self, now: Instant) -> u32 {
+        (now - self.started).as_millis() as u32
+    }
+
+    // Effects marked "keep" loop until stopped
+    pub fn is_finished(&self, now: Instant) -> bool {
+        !self.effect.keep && self.elapsed_ms(now) >= self.duration_ms()
+    }
+
+    fn track_intensity(track: &true_gear_message::Track, t: u32) -> Option<f32> {
+        let (start_time, end_time) = (track.start_time as u32, track.end_time as u32);
+        if t < start_time || t >= end_time {
+            return None;
+        }
+
+        let progress = (t - start_time) as f32 / (end_time - start_time) as f32;
+        let (from, to) = (track.start_intensity as f32, track.end_intensity as f32);
+        let intensity = match track.intensity_mode {
+            true_gear_message::IntensityMode::Const => from,
+            true_gear_message::IntensityMode::Fade => from + (to - from) * progress,
+            // ramp to the end intensity over the first half, and back over the second half
+            true_gear_message::IntensityMode::FadeInAndOut => {
+                let progress = 1.0 - (progress * 2.0 - 1.0).abs();
+                from + (to - from) * progress
+            }
+        };
+        Some(intensity)
+    }
+
+    // Per-dot intensities at t ms into the effect
+    fn sample(&self, t: u32) -> [f32; NUM_DOTS] {
+        let mut intensities = [0 as f32; NUM_DOTS];
+
+        let duration = self.duration_ms();
+        let t = if self.effect.keep && duration > 0 {
+            t % duration
+        } else {
+            t
+        };
+
+        for track in &self.effect.tracks {
+            let Some(intensity) = Self::track_intensity(track, t) else {
+                continue;
+            };
+            let channel = Channel::from(&track.action_type);
+            for &id in &track.index {
+                if let Some(dot) = Dot::from_wire_id(channel, id) {
+                    let i = dot.compact_index();
+                    intensities[i] = intensities[i].max(intensity);
+                }
+            }
+        }
+
+        intensities
+    }
+
+    pub fn render(&self, now: Instant) -> Frame {
+        let mut frame = Frame::new(Default::default());
+        // each arm keeps the interval of the first track playing on it
+        for track in self.effect.tracks.iter().rev() {
+            let channel = Channel::from(&track.action_type);
+            if channel != Channel::Electrical {
+                continue;
+            }
+            for &id in &track.index {
+                if let Some(dot) = Dot::from_wire_id(channel, id) {
+                    frame.electrical_intervals[dot.compact_index() - NUM_SHAKES] = track.interval;
+                }
+            }
+        }
+
+        let t = self.elapsed_ms(now);
+        (frame.start, frame.end) = mixer::sample_track(|offset| self.sample(t + offset));
+        frame
+    }
+}
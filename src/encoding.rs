@@ -0,0 +1,299 @@
+use crate::{
+    dot_mapping::Channel,
+    geometry::{Dot, NUM_DOTS, Zone},
+};
+use rosc::OscType;
+use serde::Deserialize;
+use std::error::Error;
+
+// selector codes of the zones, codes 1 - NUM_DOTS select single dots
+const ZONE_CODES: [(i64, Zone); 4] = [
+    (50, Zone::Front),
+    (51, Zone::Back),
+    (52, Zone::LeftArm),
+    (53, Zone::RightArm),
+];
+
+// bits of an OSC Int
+const MAX_BITMASK_DOTS: usize = 32;
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+// Compact input encodings, using fewer synced avatar parameters than one Float per dot
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EncodedInput {
+    // an Int selects a dot or a zone, a paired Float carries its intensity
+    Selector {
+        select_address: String,
+        value_address: String,
+    },
+    // each bit of an Int switches one dot, bit 0 being the first dot
+    Bitmask {
+        address: String,
+        channel: Channel,
+        dots: Vec<u8>,
+        // intensity of the dots whose bit is set
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+    },
+}
+
+// Last selection and value of a selector input
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SelectorState {
+    selection: i64,
+    value: f32,
+}
+
+impl EncodedInput {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if let EncodedInput::Bitmask {
+            address,
+            channel,
+            dots,
+            ..
+        } = self
+        {
+            if dots.len() > MAX_BITMASK_DOTS {
+                return Err(format!(
+                    "bitmask '{}' has more than {} dots",
+                    address, MAX_BITMASK_DOTS
+                )
+                .into());
+            }
+            if let Some(id) = dots
+                .iter()
+                .find(|&&id| Dot::from_wire_id(*channel, id).is_none())
+            {
+                return Err(format!(
+                    "invalid {:?} dot ID {} for bitmask '{}'",
+                    channel, id, address
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    // Compact indices of the dots selected by a selector code
+    fn selected_dots(selection: i64) -> Vec<usize> {
+        if (1..=NUM_DOTS as i64).contains(&selection) {
+            return vec![(selection - 1) as usize];
+        }
+        ZONE_CODES
+            .iter()
+            .find(|(code, _)| *code == selection)
+            .map(|(_, zone)| zone.dots().map(|dot| dot.compact_index()).collect())
+            .unwrap_or_default()
+    }
+
+    fn extract_int(arg: &OscType) -> Option<i64> {
+        match arg {
+            OscType::Int(i) => Some(*i as i64),
+            OscType::Long(l) => Some(*l),
+            OscType::Bool(b) => Some(*b as i64),
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, addr: &str) -> bool {
+        match self {
+            EncodedInput::Selector {
+                select_address,
+                value_address,
+            } => addr == select_address || addr == value_address,
+            EncodedInput::Bitmask { address, .. } => addr == address,
+        }
+    }
+
+    // Decode a message into (compact index, intensity) dot inputs, None if its value is invalid.
+    // Selector values are normalized by the caller.
+    pub fn decode(
+        &self,
+        state: &mut SelectorState,
+        addr: &str,
+        arg: &OscType,
+        value: Option<f32>,
+    ) -> Option<Vec<(usize, f32)>> {
+        match self {
+            EncodedInput::Selector { select_address, .. } => {
+                if addr == select_address {
+                    let selection = Self::extract_int(arg)?;
+                    // switch off the previous selection, and carry the value over to the new one
+                    let mut inputs: Vec<(usize, f32)> = Self::selected_dots(state.selection)
+                        .into_iter()
+                        .map(|i| (i, 0.0))
+                        .collect();
+                    inputs.extend(
+                        Self::selected_dots(selection)
+                            .into_iter()
+                            .map(|i| (i, state.value)),
+                    );
+                    state.selection = selection;
+                    Some(inputs)
+                } else {
+                    state.value = value?;
+                    Some(
+                        Self::selected_dots(state.selection)
+                            .into_iter()
+                            .map(|i| (i, state.value))
+                            .collect(),
+                    )
+                }
+            }
+            EncodedInput::Bitmask {
+                channel,
+                dots,
+                intensity,
+                ..
+            } => {
+                let bits = Self::extract_int(arg)?;
+                Some(
+                    dots.iter()
+                        .enumerate()
+                        .filter_map(|(bit, &id)| {
+                            let dot = Dot::from_wire_id(*channel, id)?;
+                            let is_set = (bits >> bit) & 1 == 1;
+                            Some((dot.compact_index(), if is_set { *intensity } else { 0.0 }))
+                        })
+                        .collect(),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selector() -> EncodedInput {
+        EncodedInput::Selector {
+            select_address: "/avatar/parameters/Select".to_string(),
+            value_address: "/avatar/parameters/Value".to_string(),
+        }
+    }
+
+    #[test]
+    fn selector_carries_the_value_over() {
+        let input = selector();
+        let mut state = SelectorState::default();
+
+        // nothing selected yet
+        let inputs = input.decode(
+            &mut state,
+            "/avatar/parameters/Value",
+            &OscType::Float(0.5),
+            Some(0.5),
+        );
+        assert_eq!(inputs, Some(vec![]));
+
+        let inputs = input.decode(
+            &mut state,
+            "/avatar/parameters/Select",
+            &OscType::Int(1),
+            None,
+        );
+        assert_eq!(inputs, Some(vec![(0, 0.5)]));
+
+        // the previous dot is switched off
+        let inputs = input.decode(
+            &mut state,
+            "/avatar/parameters/Select",
+            &OscType::Int(3),
+            None,
+        );
+        assert_eq!(inputs, Some(vec![(0, 0.0), (2, 0.5)]));
+
+        let inputs = input.decode(
+            &mut state,
+            "/avatar/parameters/Value",
+            &OscType::Float(1.0),
+            Some(1.0),
+        );
+        assert_eq!(inputs, Some(vec![(2, 1.0)]));
+    }
+
+    #[test]
+    fn selector_zone_codes() {
+        let input = selector();
+        let mut state = SelectorState::default();
+
+        let inputs = input
+            .decode(
+                &mut state,
+                "/avatar/parameters/Select",
+                &OscType::Int(52),
+                None,
+            )
+            .unwrap();
+        assert_eq!(inputs, [(Dot::LEFT_ARM.compact_index(), 0.0)]);
+
+        let inputs = input
+            .decode(
+                &mut state,
+                "/avatar/parameters/Select",
+                &OscType::Int(50),
+                None,
+            )
+            .unwrap();
+        assert_eq!(inputs.len(), 21);
+
+        // unknown codes select nothing, invalid values are ignored
+        let inputs = input.decode(
+            &mut state,
+            "/avatar/parameters/Select",
+            &OscType::Int(99),
+            None,
+        );
+        assert_eq!(inputs.map(|i| i.len()), Some(20));
+        let inputs = input.decode(
+            &mut state,
+            "/avatar/parameters/Select",
+            &OscType::Float(1.0),
+            None,
+        );
+        assert_eq!(inputs, None);
+    }
+
+    #[test]
+    fn bitmask_switches_one_dot_per_bit() {
+        let input = EncodedInput::Bitmask {
+            address: "/avatar/parameters/Mask".to_string(),
+            channel: Channel::Shake,
+            dots: vec![0, 1, 100],
+            intensity: 0.8,
+        };
+        assert!(input.validate().is_ok());
+
+        let mut state = SelectorState::default();
+        let inputs = input.decode(
+            &mut state,
+            "/avatar/parameters/Mask",
+            &OscType::Int(0b101),
+            None,
+        );
+        assert_eq!(
+            inputs,
+            Some(vec![
+                (Dot::front(0, 0).compact_index(), 0.8),
+                (Dot::front(0, 1).compact_index(), 0.0),
+                (Dot::back(0, 0).compact_index(), 0.8),
+            ])
+        );
+    }
+
+    #[test]
+    fn invalid_bitmasks_are_rejected() {
+        let input = EncodedInput::Bitmask {
+            address: "/avatar/parameters/Mask".to_string(),
+            channel: Channel::Shake,
+            dots: vec![20],
+            intensity: 1.0,
+        };
+        assert!(input.validate().is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use crate::{
+    geometry::{Dot, NUM_DOTS, NUM_SHAKES, Zone},
+    mixer::{self, SAMPLE_STEP_MS},
+    spatial::DirectionConfig,
+};
+use tokio::time::Instant;
+
+// /truegear/generator/<kind> starts (true / non-zero) or stops (false / 0) a generator,
+// /truegear/generator/<kind>/<param> sets one of its parameters
+pub const GENERATOR_ROOT: &str = "/truegear/generator";
+
+// distance travelled by a ripple over one period, enough to cross a side from a corner
+const RIPPLE_RADIUS: f32 = 3.0;
+
+// length of a rain drop
+const DROP_MS: f32 = 80.0;
+
+// most drops playing at once, whatever the period
+const MAX_OVERLAPPING_DROPS: u64 = 8;
+
+// turns of a spiral around the torso over one period, while descending from the top row
+const SPIRAL_TURNS: f32 = 2.0;
+const SPIRAL_SPREAD_DEG: f32 = 60.0;
+
+// the wearer's left chest
+pub const HEART_DOTS: [Dot; 4] = [
+    Dot::front(0, 0),
+    Dot::front(0, 1),
+    Dot::front(1, 0),
+    Dot::front(1, 1),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneratorKind {
+    // a band sweeping from the top to the bottom of both sides
+    Wave,
+    // a ring expanding from a point of one side
+    Ripple,
+    // a double beat on the chest
+    Heartbeat,
+    // drops falling on random dots
+    Rain,
+    // a spot circling the torso while descending
+    Spiral,
+}
+
+impl GeneratorKind {
+    pub const ALL: [GeneratorKind; 5] = [
+        GeneratorKind::Wave,
+        GeneratorKind::Ripple,
+        GeneratorKind::Heartbeat,
+        GeneratorKind::Rain,
+        GeneratorKind::Spiral,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GeneratorKind::Wave => "wave",
+            GeneratorKind::Ripple => "ripple",
+            GeneratorKind::Heartbeat => "heartbeat",
+            GeneratorKind::Rain => "rain",
+            GeneratorKind::Spiral => "spiral",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    // length of a cycle; for rain, time between two drops
+    fn default_period_ms(&self) -> f32 {
+        match self {
+            GeneratorKind::Wave => 1000.0,
+            GeneratorKind::Ripple => 800.0,
+            GeneratorKind::Heartbeat => 1000.0,
+            GeneratorKind::Rain => 100.0,
+            GeneratorKind::Spiral => 2000.0,
+        }
+    }
+}
+
+// Parses a generator address into its kind, and the parameter it sets if any
+pub fn parse_address(addr: &str) -> Option<(GeneratorKind, Option<&str>)> {
+    let rest = addr.strip_prefix(GENERATOR_ROOT)?.strip_prefix('/')?;
+    let (kind, param) = match rest.split_once('/') {
+        Some((kind, param)) => (kind, Some(param)),
+        None => (rest, None),
+    };
+    Some((GeneratorKind::from_name(kind)?, param))
+}
+
+// Parameters of a generator, kept while it is stopped
+#[derive(Debug, Clone, Copy)]
+pub struct GeneratorParams {
+    // 0.0 - 1.0, scaled by the channel intensity
+    pub intensity: f32,
+    pub period_ms: f32,
+    // size of the wave band, ripple ring and spiral spot, in Dot::position units
+    pub width: f32,
+    // origin of the ripple
+    pub x: f32,
+    pub y: f32,
+    pub side: Zone,
+}
+
+impl GeneratorParams {
+    fn new(kind: GeneratorKind) -> Self {
+        Self {
+            intensity: 1.0,
+            period_ms: kind.default_period_ms(),
+            width: 0.5,
+            x: 0.0,
+            y: 0.0,
+            side: Zone::Front,
+        }
+    }
+
+    // Set a numeric parameter by name, returns whether the name and value are valid.
+    // Periods are clamped to the sampling resolution, shorter ones could not be rendered.
+    pub fn set(&mut self, name: &str, value: f32) -> bool {
+        if !value.is_finite() {
+            return false;
+        }
+        match name {
+            "intensity" if value >= 0.0 => self.intensity = value,
+            "period_ms" if value > 0.0 => self.period_ms = value.max(SAMPLE_STEP_MS as f32),
+            "width" if value > 0.0 => self.width = value,
+            "x" => self.x = value.clamp(-1.0, 1.0),
+            "y" => self.y = value.clamp(-1.0, 1.0),
+            _ => return false,
+        }
+        true
+    }
+}
+
+// Level of a heartbeat t ms into a beat: a strong beat followed by a weaker one.
+// The pair keeps its timing up to 60 BPM, and tightens at faster rates.
+pub fn heartbeat_level(t_ms: f32, period_ms: f32) -> f32 {
+    let beat = period_ms.min(1000.0);
+    let t = t_ms.rem_euclid(period_ms) / beat;
+    if t < 0.12 {
+        1.0
+    } else if (0.3..0.42).contains(&t) {
+        0.6
+    } else {
+        0.0
+    }
+}
+
+// A procedural pattern, rendered tick by tick into per-dot levels
+#[derive(Debug, Clone)]
+pub struct Generator {
+    pub kind: GeneratorKind,
+    pub params: GeneratorParams,
+    started: Option<Instant>,
+}
+
+impl Generator {
+    pub fn new(kind: GeneratorKind) -> Self {
+        Self {
+            kind,
+            params: GeneratorParams::new(kind),
+            started: None,
+        }
+    }
+
+    pub fn start(&mut self, now: Instant) {
+        self.started = Some(now);
+    }
+
+    pub fn stop(&mut self) {
+        self.started = None;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.started.is_some()
+    }
+
+    // Dot for a rain drop, spread pseudo-randomly over the shake dots
+    fn drop_dot(drop: u64) -> usize {
+        // splitmix64 finalizer
+        let mut z = drop.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z % NUM_SHAKES as u64) as usize
+    }
+
+    // Per-dot levels at t ms after the start
+    fn sample(&self, t: f32) -> [f32; NUM_DOTS] {
+        let mut levels = [0 as f32; NUM_DOTS];
+        let params = &self.params;
+        let phase = (t / params.period_ms).fract();
+
+        match self.kind {
+            GeneratorKind::Wave => {
+                // the band enters above the top row and leaves below the bottom row
+                let center = 1.0 + params.width - phase * (2.0 + 2.0 * params.width);
+                for dot in [Zone::Front, Zone::Back].into_iter().flat_map(|z| z.dots()) {
+                    let (_, y) = dot.position();
+                    levels[dot.compact_index()] =
+                        (1.0 - (y - center).abs() / params.width).max(0.0);
+                }
+            }
+            GeneratorKind::Ripple => {
+                let radius = phase * RIPPLE_RADIUS;
+                for dot in params.side.dots() {
+                    let (x, y) = dot.position();
+                    let distance = (x - params.x).hypot(y - params.y);
+                    let ring = (1.0 - (distance - radius).abs() / params.width).max(0.0);
+                    // fade out as the ring spreads
+                    levels[dot.compact_index()] = ring * (1.0 - phase);
+                }
+            }
+            GeneratorKind::Heartbeat => {
+                let level = heartbeat_level(t, params.period_ms);
+                for dot in HEART_DOTS {
+                    levels[dot.compact_index()] = level;
+                }
+            }
+            GeneratorKind::Rain => {
+                // drops started within the last DROP_MS
+                let current = (t / params.period_ms) as u64;
+                let overlapping =
+                    ((DROP_MS / params.period_ms).ceil() as u64).min(MAX_OVERLAPPING_DROPS);
+                for drop in current.saturating_sub(overlapping)..=current {
+                    if t - drop as f32 * params.period_ms < DROP_MS {
+                        levels[Self::drop_dot(drop)] = 1.0;
+                    }
+                }
+            }
+            GeneratorKind::Spiral => {
+                let spread = DirectionConfig {
+                    spread_deg: SPIRAL_SPREAD_DEG,
+                    height_spread: params.width,
+                };
+                let angle = phase * SPIRAL_TURNS * 360.0;
+                let height = 1.0 - 2.0 * phase;
+                for (i, weight) in spread.spread(angle, Some(height)) {
+                    levels[i] = weight;
+                }
+            }
+        }
+
+        for level in levels.iter_mut() {
+            *level *= params.intensity;
+        }
+        levels
+    }
+
+    // Per-dot levels at the start and the end of the next track, None if stopped
+    pub fn render(&self, now: Instant) -> Option<([f32; NUM_DOTS], [f32; NUM_DOTS])> {
+        let t = (now - self.started?).as_millis() as u32;
+        Some(mixer::sample_track(|offset| {
+            self.sample((t + offset) as f32)
+        }))
+    }
+}
+
+// Every generator, stopped
+pub fn generators() -> Vec<Generator> {
+    GeneratorKind::ALL.into_iter().map(Generator::new).collect()
+}
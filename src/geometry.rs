@@ -0,0 +1,381 @@
+use crate::dot_mapping::Channel;
+use serde::Deserialize;
+use std::error::Error;
+
+// number of rows and columns of shake dots on each side of the vest
+pub const NUM_ROWS: u8 = 5;
+pub const NUM_COLUMNS: u8 = 4;
+
+pub const NUM_SHAKES: usize = 40;
+pub const NUM_ELECTRICAL: usize = 2;
+pub const NUM_DOTS: usize = NUM_SHAKES + NUM_ELECTRICAL;
+
+// shake IDs of the back side, and the right arm electrical ID, are offset by this in TrueGear's
+// wire numbering
+const BACK_ID_OFFSET: u8 = 100;
+
+// horizontal position of the arms, outside of the shake columns
+const ARM_X: f32 = 1.5;
+
+// letter of each column in DOTS, A being 0: the front columns are A, B, H and G, mirrored, and
+// the back columns C to F
+const FRONT_COLUMN_LETTERS: [usize; NUM_COLUMNS as usize] = [1, 0, 7, 6];
+const BACK_COLUMN_LETTERS: [usize; NUM_COLUMNS as usize] = [2, 3, 4, 5];
+
+// azimuth of the outermost columns, the columns of both sides form an evenly spaced ring
+const OUTER_COLUMN_AZIMUTH: f32 = 67.5;
+
+pub const DOT_NAMES: [&str; NUM_DOTS] = [
+    // shake dots first
+    "TrueGearA1",
+    "TrueGearA2",
+    "TrueGearA3",
+    "TrueGearA4",
+    "TrueGearA5",
+    "TrueGearB1",
+    "TrueGearB2",
+    "TrueGearB3",
+    "TrueGearB4",
+    "TrueGearB5",
+    "TrueGearC1",
+    "TrueGearC2",
+    "TrueGearC3",
+    "TrueGearC4",
+    "TrueGearC5",
+    "TrueGearD1",
+    "TrueGearD2",
+    "TrueGearD3",
+    "TrueGearD4",
+    "TrueGearD5",
+    "TrueGearE1",
+    "TrueGearE2",
+    "TrueGearE3",
+    "TrueGearE4",
+    "TrueGearE5",
+    "TrueGearF1",
+    "TrueGearF2",
+    "TrueGearF3",
+    "TrueGearF4",
+    "TrueGearF5",
+    "TrueGearG1",
+    "TrueGearG2",
+    "TrueGearG3",
+    "TrueGearG4",
+    "TrueGearG5",
+    "TrueGearH1",
+    "TrueGearH2",
+    "TrueGearH3",
+    "TrueGearH4",
+    "TrueGearH5",
+    // then electrical dots
+    "TrueGearArmL",
+    "TrueGearArmR",
+];
+
+pub const DOTS: [Dot; NUM_DOTS] = [
+    // shake dots first
+    // A: front column 1
+    Dot::front(0, 1),
+    Dot::front(1, 1),
+    Dot::front(2, 1),
+    Dot::front(3, 1),
+    Dot::front(4, 1),
+    // B: front column 0
+    Dot::front(0, 0),
+    Dot::front(1, 0),
+    Dot::front(2, 0),
+    Dot::front(3, 0),
+    Dot::front(4, 0),
+    // C: back column 0
+    Dot::back(0, 0),
+    Dot::back(1, 0),
+    Dot::back(2, 0),
+    Dot::back(3, 0),
+    Dot::back(4, 0),
+    // D: back column 1
+    Dot::back(0, 1),
+    Dot::back(1, 1),
+    Dot::back(2, 1),
+    Dot::back(3, 1),
+    Dot::back(4, 1),
+    // E: back column 2
+    Dot::back(0, 2),
+    Dot::back(1, 2),
+    Dot::back(2, 2),
+    Dot::back(3, 2),
+    Dot::back(4, 2),
+    // F: back column 3
+    Dot::back(0, 3),
+    Dot::back(1, 3),
+    Dot::back(2, 3),
+    Dot::back(3, 3),
+    Dot::back(4, 3),
+    // G: front column 3
+    Dot::front(0, 3),
+    Dot::front(1, 3),
+    Dot::front(2, 3),
+    Dot::front(3, 3),
+    Dot::front(4, 3),
+    // H: front column 2
+    Dot::front(0, 2),
+    Dot::front(1, 2),
+    Dot::front(2, 2),
+    Dot::front(3, 2),
+    Dot::front(4, 2),
+    // then electrical dots
+    Dot::LEFT_ARM,
+    Dot::RIGHT_ARM,
+];
+
+// Compact indices of dot names, unknown names being rejected
+pub fn parse_dot_names(names: &[String]) -> Result<Vec<usize>, Box<dyn Error>> {
+    names
+        .iter()
+        .map(|name| {
+            Dot::from_name(name)
+                .map(|dot| dot.compact_index())
+                .ok_or_else(|| format!("unknown dot '{}'", name).into())
+        })
+        .collect()
+}
+
+// A part of the vest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Zone {
+    Front,
+    Back,
+    LeftArm,
+    RightArm,
+}
+
+impl Zone {
+    pub fn channel(&self) -> Channel {
+        match self {
+            Zone::Front | Zone::Back => Channel::Shake,
+            Zone::LeftArm | Zone::RightArm => Channel::Electrical,
+        }
+    }
+
+    // the other zone on the same channel
+    pub fn opposite(&self) -> Zone {
+        match self {
+            Zone::Front => Zone::Back,
+            Zone::Back => Zone::Front,
+            Zone::LeftArm => Zone::RightArm,
+            Zone::RightArm => Zone::LeftArm,
+        }
+    }
+
+    pub fn dots(&self) -> impl Iterator<Item = Dot> + use<> {
+        let zone = *self;
+        DOTS.into_iter().filter(move |dot| dot.zone == zone)
+    }
+}
+
+// A single dot of the vest: a shake motor at a row and column of the front or back,
+// or the electrical channel of an arm.
+// Rows run from the top (0) to the bottom, columns from the wearer's left (0) to right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Dot {
+    zone: Zone,
+    row: u8,
+    column: u8,
+}
+
+impl Dot {
+    pub const LEFT_ARM: Dot = Dot {
+        zone: Zone::LeftArm,
+        row: 0,
+        column: 0,
+    };
+
+    pub const RIGHT_ARM: Dot = Dot {
+        zone: Zone::RightArm,
+        row: 0,
+        column: 0,
+    };
+
+    // A shake dot, panics if out of the grid
+    pub const fn front(row: u8, column: u8) -> Dot {
+        assert!(row < NUM_ROWS && column < NUM_COLUMNS);
+        Dot {
+            zone: Zone::Front,
+            row,
+            column,
+        }
+    }
+
+    // A shake dot, panics if out of the grid
+    pub const fn back(row: u8, column: u8) -> Dot {
+        assert!(row < NUM_ROWS && column < NUM_COLUMNS);
+        Dot {
+            zone: Zone::Back,
+            row,
+            column,
+        }
+    }
+
+    // The dot at a row and column of a zone, arms only have row 0 and column 0
+    pub fn new(zone: Zone, row: u8, column: u8) -> Option<Dot> {
+        match zone {
+            Zone::Front | Zone::Back if row < NUM_ROWS && column < NUM_COLUMNS => {
+                Some(Dot { zone, row, column })
+            }
+            Zone::LeftArm | Zone::RightArm if row == 0 && column == 0 => {
+                Some(Dot { zone, row, column })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn zone(&self) -> Zone {
+        self.zone
+    }
+
+    pub fn row(&self) -> u8 {
+        self.row
+    }
+
+    pub fn column(&self) -> u8 {
+        self.column
+    }
+
+    pub fn channel(&self) -> Channel {
+        self.zone.channel()
+    }
+
+    // Avatar parameter name of the dot, e.g. "TrueGearA1"
+    pub const fn name(&self) -> &'static str {
+        DOT_NAMES[self.compact_index()]
+    }
+
+    pub fn from_name(name: &str) -> Option<Dot> {
+        DOT_NAMES.iter().position(|&n| n == name).map(|i| DOTS[i])
+    }
+
+    // Parse a dot ID in TrueGear's wire numbering:
+    // shake dots are 0-19 on the front and 100-119 on the back, row by row;
+    // electrical dots are 0 for the left arm and 100 for the right arm
+    pub fn from_wire_id(channel: Channel, id: u8) -> Option<Dot> {
+        match channel {
+            Channel::Shake => {
+                let zone = if id >= BACK_ID_OFFSET {
+                    Zone::Back
+                } else {
+                    Zone::Front
+                };
+                let offset = id % BACK_ID_OFFSET;
+                if id - offset != 0 && id - offset != BACK_ID_OFFSET {
+                    return None;
+                }
+                Dot::new(zone, offset / NUM_COLUMNS, offset % NUM_COLUMNS)
+            }
+            Channel::Electrical => match id {
+                0 => Some(Dot::LEFT_ARM),
+                BACK_ID_OFFSET => Some(Dot::RIGHT_ARM),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn wire_id(&self) -> u8 {
+        match self.zone {
+            Zone::Front => self.row * NUM_COLUMNS + self.column,
+            Zone::Back => BACK_ID_OFFSET + self.row * NUM_COLUMNS + self.column,
+            Zone::LeftArm => 0,
+            Zone::RightArm => BACK_ID_OFFSET,
+        }
+    }
+
+    // Index of the dot in DOTS and in the per-dot state arrays
+    pub const fn compact_index(&self) -> usize {
+        let letter = match self.zone {
+            Zone::Front => FRONT_COLUMN_LETTERS[self.column as usize],
+            Zone::Back => BACK_COLUMN_LETTERS[self.column as usize],
+            Zone::LeftArm => return NUM_SHAKES,
+            Zone::RightArm => return NUM_SHAKES + 1,
+        };
+        letter * NUM_ROWS as usize + self.row as usize
+    }
+
+    // Position on the vest: x from -1.0 at the left column to 1.0 at the right column,
+    // y from 1.0 at the top row to -1.0 at the bottom row. The arms sit beside the top row.
+    pub fn position(&self) -> (f32, f32) {
+        let half_width = (NUM_COLUMNS - 1) as f32 / 2.0;
+        let half_height = (NUM_ROWS - 1) as f32 / 2.0;
+        match self.zone {
+            Zone::Front | Zone::Back => (
+                (self.column as f32 - half_width) / half_width,
+                (half_height - self.row as f32) / half_height,
+            ),
+            Zone::LeftArm => (-ARM_X, 1.0),
+            Zone::RightArm => (ARM_X, 1.0),
+        }
+    }
+
+    // Direction of the dot around the torso, in degrees clockwise seen from above:
+    // 0 is straight ahead, 90 the wearer's right, 180 behind and -90 the wearer's left
+    pub fn azimuth(&self) -> f32 {
+        let (x, _) = self.position();
+        match self.zone {
+            Zone::Front => x * OUTER_COLUMN_AZIMUTH,
+            Zone::Back => 180.0 - x * OUTER_COLUMN_AZIMUTH,
+            Zone::LeftArm => -90.0,
+            Zone::RightArm => 90.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // TrueGear wire IDs of DOTS, as originally listed by hand
+    const DOT_IDS: [u8; NUM_DOTS] = [
+        1, 5, 9, 13, 17, 0, 4, 8, 12, 16, 100, 104, 108, 112, 116, 101, 105, 109, 113, 117, 102,
+        106, 110, 114, 118, 103, 107, 111, 115, 119, 3, 7, 11, 15, 19, 2, 6, 10, 14, 18, 0, 100,
+    ];
+
+    #[test]
+    fn wire_ids_round_trip() {
+        for (i, dot) in DOTS.into_iter().enumerate() {
+            assert_eq!(dot.wire_id(), DOT_IDS[i], "{}", DOT_NAMES[i]);
+            assert_eq!(Dot::from_wire_id(dot.channel(), dot.wire_id()), Some(dot));
+            assert_eq!(dot.compact_index(), i, "{}", DOT_NAMES[i]);
+        }
+    }
+
+    #[test]
+    fn invalid_wire_ids_are_rejected() {
+        for id in [20, 50, 120, 200] {
+            assert_eq!(Dot::from_wire_id(Channel::Shake, id), None, "shake {}", id);
+        }
+        assert_eq!(Dot::from_wire_id(Channel::Electrical, 1), None);
+    }
+
+    #[test]
+    fn dot_names_round_trip() {
+        for (i, dot) in DOTS.into_iter().enumerate() {
+            assert_eq!(dot.name(), DOT_NAMES[i]);
+            assert_eq!(Dot::from_name(dot.name()), Some(dot));
+        }
+        assert_eq!(Dot::from_name("TrueGearI1"), None);
+
+        let names = ["TrueGearB1".to_string(), "TrueGearArmR".to_string()];
+        assert_eq!(parse_dot_names(&names).unwrap(), [5, 41]);
+        assert!(parse_dot_names(&["TrueGearI1".to_string()]).is_err());
+    }
+
+    #[test]
+    fn zones_cover_every_dot_once() {
+        let mut count = [0; NUM_DOTS];
+        for zone in [Zone::Front, Zone::Back, Zone::LeftArm, Zone::RightArm] {
+            for dot in zone.dots() {
+                assert_eq!(dot.channel(), zone.channel());
+                count[dot.compact_index()] += 1;
+            }
+        }
+        assert_eq!(count, [1; NUM_DOTS]);
+    }
+}
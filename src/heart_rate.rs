@@ -0,0 +1,148 @@
+use crate::{
+    generator::{HEART_DOTS, heartbeat_level},
+    geometry::parse_dot_names,
+    mixer,
+};
+use rosc::OscType;
+use serde::Deserialize;
+use std::{error::Error, time::Duration};
+use tokio::time::Instant;
+
+fn default_dots() -> Vec<String> {
+    HEART_DOTS
+        .iter()
+        .map(|dot| dot.name().to_string())
+        .collect()
+}
+
+fn default_intensity() -> f32 {
+    0.6
+}
+
+fn default_float_full_scale_bpm() -> f32 {
+    255.0
+}
+
+fn default_min_bpm() -> f32 {
+    30.0
+}
+
+fn default_max_bpm() -> f32 {
+    240.0
+}
+
+fn default_timeout_ms() -> u64 {
+    10000
+}
+
+// Plays a heartbeat on the chest in sync with a streamed heart rate
+#[derive(Debug, Clone, Deserialize)]
+pub struct HeartRateConfig {
+    // heart rate input: Ints in BPM, Floats as a fraction of float_full_scale_bpm
+    pub address: String,
+    // dot names, defaults to the wearer's left chest
+    #[serde(default = "default_dots")]
+    pub dots: Vec<String>,
+    // 0.0 - 1.0, scaled by the shake intensity
+    #[serde(default = "default_intensity")]
+    pub intensity: f32,
+    #[serde(default = "default_float_full_scale_bpm")]
+    pub float_full_scale_bpm: f32,
+    // heart rates outside of this range stop the heartbeat, e.g. 0 from a disconnected sensor
+    #[serde(default = "default_min_bpm")]
+    pub min_bpm: f32,
+    #[serde(default = "default_max_bpm")]
+    pub max_bpm: f32,
+    // stop the heartbeat once the heart rate has not been received for this long (0 to disable)
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl HeartRateConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        parse_dot_names(&self.dots)?;
+        if self.min_bpm <= 0.0 || self.min_bpm >= self.max_bpm {
+            return Err(format!("invalid BPM range {} - {}", self.min_bpm, self.max_bpm).into());
+        }
+        if self.intensity < 0.0 || self.float_full_scale_bpm <= 0.0 {
+            return Err("intensity and float_full_scale_bpm must be positive".into());
+        }
+        Ok(())
+    }
+
+    // Compact indices of the dots the heartbeat plays on, names are checked when loading
+    pub fn members(&self) -> Vec<usize> {
+        parse_dot_names(&self.dots).unwrap_or_default()
+    }
+
+    pub fn extract_bpm(&self, arg: &OscType) -> Option<f32> {
+        match arg {
+            OscType::Int(i) => Some(*i as f32),
+            OscType::Long(l) => Some(*l as f32),
+            OscType::Float(f) => Some(f * self.float_full_scale_bpm),
+            OscType::Double(f) => Some(*f as f32 * self.float_full_scale_bpm),
+            OscType::String(s) => s.trim().parse::<f32>().ok(),
+            _ => None,
+        }
+    }
+}
+
+// Current heart rate, and position within the current beat
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeartRateState {
+    bpm: Option<f32>,
+    // 0.0 - 1.0 through the beat, at phase_time
+    phase: f32,
+    phase_time: Option<Instant>,
+    last_update: Option<Instant>,
+}
+
+impl HeartRateState {
+    // Follow a new heart rate; the beat keeps its phase so changes stay smooth.
+    // Returns whether the heartbeat started.
+    pub fn set_bpm(&mut self, config: &HeartRateConfig, bpm: f32, now: Instant) -> bool {
+        self.last_update = Some(now);
+        if !(config.min_bpm..=config.max_bpm).contains(&bpm) {
+            if self.bpm.take().is_some() {
+                tracing::info!("Heart rate {} out of range, heartbeat stopped", bpm);
+            }
+            return false;
+        }
+        if self.bpm.replace(bpm).is_some() {
+            return false;
+        }
+        // start on a beat
+        self.phase = 0.0;
+        self.phase_time = Some(now);
+        true
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    // Advance the beat to now, and return the level at the start and the end of the next
+    // track, None while no heart rate is received
+    pub fn render(&mut self, config: &HeartRateConfig, now: Instant) -> Option<(f32, f32)> {
+        let timeout = Duration::from_millis(config.timeout_ms);
+        if config.timeout_ms > 0 && self.last_update.is_some_and(|t| now - t >= timeout) {
+            if self.bpm.is_some() {
+                tracing::warn!(
+                    "No heart rate in {} ms, heartbeat stopped",
+                    config.timeout_ms
+                );
+            }
+            self.reset();
+        }
+
+        let period_ms = 60000.0 / self.bpm?;
+        let elapsed = self.phase_time.map_or(Duration::ZERO, |t| now - t);
+        self.phase = (self.phase + elapsed.as_secs_f32() * 1000.0 / period_ms).fract();
+        self.phase_time = Some(now);
+
+        let beat_ms = self.phase * period_ms;
+        let ([start], [end]) =
+            mixer::sample_track(|offset| [heartbeat_level(beat_ms + offset as f32, period_ms)]);
+        Some((start * config.intensity, end * config.intensity))
+    }
+}
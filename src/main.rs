@@ -1,19 +1,56 @@
 use crate::{reciver::Reciver, sender::Sender};
-use clap::Parser;
-use mapping::ProtocalMapper;
-use std::{error::Error, net::SocketAddr};
+use calibration::Calibration;
+use clap::{Parser, Subcommand};
+use config::Config;
+use control::RuntimeSettings;
+use dot_mapping::DotMapping;
+use effect_library::EffectLibrary;
+use mapping::{IntRange, ProtocalMapper};
+use std::{error::Error, net::SocketAddr, path::PathBuf};
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
 
+mod calibration;
+mod config;
+mod control;
+mod curve;
+mod dot_mapping;
+mod effect_library;
+mod encoding;
+mod generator;
+mod geometry;
+mod heart_rate;
 mod mapping;
+mod mixer;
 mod reciver;
 mod sender;
+mod smoothing;
+mod spatial;
 mod true_gear_message;
+mod vrchat;
 mod websocket;
 
+#[derive(Subcommand, Clone)]
+enum Commands {
+    // Calibration wizard
+    #[command(about = "Play each dot in turn, rate its strength and write a per-dot gain file")]
+    Calibrate {
+        #[arg(
+            short,
+            long,
+            default_value = "calibration.json",
+            help = "Calibration file to write"
+        )]
+        output: PathBuf,
+    },
+}
+
 #[derive(Parser, Clone)]
 #[command(version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     // OSC receive port
     #[arg(
         short,
@@ -53,6 +90,31 @@ struct Args {
     #[arg(long, default_value_t = 10, help = "Electrical interval")]
     electrical_interval: u8,
 
+    // Per-arm electrical overrides
+    #[arg(
+        long,
+        help = "Left arm electrical intensity (defaults to --electrical-intensity)"
+    )]
+    left_arm_intensity: Option<u16>,
+
+    #[arg(
+        long,
+        help = "Right arm electrical intensity (defaults to --electrical-intensity)"
+    )]
+    right_arm_intensity: Option<u16>,
+
+    #[arg(
+        long,
+        help = "Left arm electrical interval (defaults to --electrical-interval)"
+    )]
+    left_arm_interval: Option<u8>,
+
+    #[arg(
+        long,
+        help = "Right arm electrical interval (defaults to --electrical-interval)"
+    )]
+    right_arm_interval: Option<u8>,
+
     // Feedback mode
     #[arg(
         long,
@@ -61,6 +123,48 @@ struct Args {
     )]
     feedback_mode: mapping::FeedbackMode,
 
+    // Integer input range
+    #[arg(
+        long,
+        default_value_t = 0,
+        help = "Integer input value mapped to zero intensity"
+    )]
+    int_min: i64,
+
+    #[arg(
+        long,
+        default_value_t = 255,
+        help = "Integer input value mapped to full intensity"
+    )]
+    int_max: i64,
+
+    // Mapping file
+    #[arg(
+        short,
+        long,
+        help = "JSON file mapping OSC addresses to TrueGear dots (built-in mapping if omitted)"
+    )]
+    mapping: Option<PathBuf>,
+
+    // Config file
+    #[arg(short, long, help = "JSON config file for feedback tuning")]
+    config: Option<PathBuf>,
+
+    // Effect library directory
+    #[arg(
+        short,
+        long,
+        help = "Directory of TrueGear effect JSON files that can be triggered over OSC"
+    )]
+    effects_dir: Option<PathBuf>,
+
+    // Calibration file
+    #[arg(
+        long,
+        help = "Per-dot gain file written by the calibrate subcommand (no calibration if omitted)"
+    )]
+    calibration: Option<PathBuf>,
+
     // show debug logs
     #[arg(short, long, default_value_t = false, help = "Enable verbose logging")]
     verbose: bool,
@@ -84,6 +188,17 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     setup_logging(log_level);
 
+    if let Some(Commands::Calibrate { output }) = &args.command {
+        return calibration::run_wizard(
+            args.truegear_ws_url.clone(),
+            args.shake_intensity,
+            args.electrical_intensity,
+            args.electrical_interval,
+            output,
+        )
+        .await;
+    }
+
     let forward_addr: Option<SocketAddr> = if args.forward_osc_port != 0 {
         if args.receive_osc_port == args.forward_osc_port {
             return Err("receive_port and send_port must differ when forwarding is enabled".into());
@@ -99,18 +214,104 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let recv_addr: SocketAddr = format!("0.0.0.0:{}", args.receive_osc_port).parse()?;
 
-    let protocol_mapper = ProtocalMapper::new(args.feedback_mode);
+    let dot_mapping = match &args.mapping {
+        Some(path) => {
+            let dot_mapping = DotMapping::load(path)?;
+            tracing::info!(
+                "Loaded {} mapped addresses from {}",
+                dot_mapping.len(),
+                path.display()
+            );
+            dot_mapping
+        }
+        None => DotMapping::builtin(),
+    };
+
+    for (name, dot) in geometry::DOT_NAMES.iter().zip(geometry::DOTS) {
+        tracing::debug!(
+            "{}: {:?} row {} column {}, wire ID {}, position {:?}",
+            name,
+            dot.zone(),
+            dot.row(),
+            dot.column(),
+            dot.wire_id(),
+            dot.position()
+        );
+    }
+
+    if args.int_min == args.int_max {
+        return Err("int_min and int_max must differ".into());
+    }
+
+    let int_range = IntRange {
+        min: args.int_min,
+        max: args.int_max,
+    };
+
+    let config = match &args.config {
+        Some(path) => {
+            let config = Config::load(path)?;
+            tracing::info!("Loaded config from {}", path.display());
+            config
+        }
+        None => Config::default(),
+    };
+
+    if config.stale_timeout_ms > 0 {
+        tracing::info!("Stale input timeout: {} ms", config.stale_timeout_ms);
+    } else {
+        tracing::info!("Stale input timeout: disabled");
+    }
+    tracing::info!("Layout: {:?}", config.layout);
+
+    let settings = RuntimeSettings {
+        shake_intensity: args.shake_intensity,
+        electrical_intensity: [
+            args.left_arm_intensity.unwrap_or(args.electrical_intensity),
+            args.right_arm_intensity
+                .unwrap_or(args.electrical_intensity),
+        ],
+        electrical_interval: [
+            args.left_arm_interval.unwrap_or(args.electrical_interval),
+            args.right_arm_interval.unwrap_or(args.electrical_interval),
+        ],
+        feedback_mode: args.feedback_mode,
+    };
+
+    let effect_library = match &args.effects_dir {
+        Some(dir) => {
+            let effect_library = EffectLibrary::load(dir)?;
+            tracing::info!(
+                "Loaded effects from {}: {}",
+                dir.display(),
+                effect_library.names().collect::<Vec<_>>().join(", ")
+            );
+            effect_library
+        }
+        None => EffectLibrary::default(),
+    };
+
+    let calibration = match &args.calibration {
+        Some(path) => {
+            let calibration = Calibration::load(path)?;
+            tracing::info!("Loaded calibration from {}", path.display());
+            calibration
+        }
+        None => Calibration::default(),
+    };
+
+    let protocol_mapper = ProtocalMapper::new(
+        settings,
+        dot_mapping,
+        int_range,
+        config,
+        effect_library,
+        calibration,
+    );
 
     let reciver = Reciver::build(recv_addr, protocol_mapper.clone(), forward_addr).await;
 
-    let mut sender = Sender::build(
-        args.truegear_ws_url,
-        protocol_mapper.clone(),
-        args.shake_intensity,
-        args.electrical_intensity,
-        args.electrical_interval,
-    )
-    .await?;
+    let mut sender = Sender::build(args.truegear_ws_url, protocol_mapper.clone()).await?;
 
     let mut reciver_clone = reciver.clone();
     let mut sender_clone = sender.clone();
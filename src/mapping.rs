@@ -1,158 +1,1125 @@
-use crate::true_gear_message;
+use crate::{
+    calibration::Calibration,
+    config::{ChannelConfig, Config},
+    control::{Command, GestureCommand, MAX_INTENSITY, RuntimeSettings},
+    dot_mapping::{Channel, DotMapping},
+    effect_library::{EffectLibrary, EffectTrigger, PlayingEffect, STOP_ADDRESS},
+    encoding::SelectorState,
+    generator::{self, Generator},
+    geometry::{DOT_NAMES, DOTS, Dot, NUM_COLUMNS, NUM_DOTS, NUM_ELECTRICAL, NUM_ROWS, NUM_SHAKES},
+    heart_rate::HeartRateState,
+    mixer::{Frame, Layer, Mixer, TRACK_DURATION_MS},
+    smoothing::{Smoothing, SmoothingState},
+    spatial::{self, DIRECTION_ADDRESS, POINT_ADDRESS},
+    true_gear_message,
+    vrchat::{Reaction, VrchatState},
+};
 use rosc::{OscMessage, OscPacket, OscType};
-use std::{
-    collections::HashMap,
-    sync::{Arc, OnceLock},
+use serde::Deserialize;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{
+    sync::{Mutex, Notify},
+    time::Instant,
 };
-use tokio::sync::Mutex;
-
-const NUM_SHAKES: usize = 40;
-const NUM_ELECTRICAL: usize = 2;
-const NUM_DOTS: usize = NUM_SHAKES + NUM_ELECTRICAL;
-
-const DOT_NAMES: [&str; NUM_DOTS] = [
-    // shake dots first
-    "TrueGearA1",
-    "TrueGearA2",
-    "TrueGearA3",
-    "TrueGearA4",
-    "TrueGearA5",
-    "TrueGearB1",
-    "TrueGearB2",
-    "TrueGearB3",
-    "TrueGearB4",
-    "TrueGearB5",
-    "TrueGearC1",
-    "TrueGearC2",
-    "TrueGearC3",
-    "TrueGearC4",
-    "TrueGearC5",
-    "TrueGearD1",
-    "TrueGearD2",
-    "TrueGearD3",
-    "TrueGearD4",
-    "TrueGearD5",
-    "TrueGearE1",
-    "TrueGearE2",
-    "TrueGearE3",
-    "TrueGearE4",
-    "TrueGearE5",
-    "TrueGearF1",
-    "TrueGearF2",
-    "TrueGearF3",
-    "TrueGearF4",
-    "TrueGearF5",
-    "TrueGearG1",
-    "TrueGearG2",
-    "TrueGearG3",
-    "TrueGearG4",
-    "TrueGearG5",
-    "TrueGearH1",
-    "TrueGearH2",
-    "TrueGearH3",
-    "TrueGearH4",
-    "TrueGearH5",
-    // then electrical dots
-    "TrueGearArmL",
-    "TrueGearArmR",
-];
-
-const DOT_IDS: [u8; NUM_DOTS] = [
-    // shake dot IDs in TrueGear's defination
-    1, 5, 9, 13, 17, 0, 4, 8, 12, 16, 100, 104, 108, 112, 116, 101, 105, 109, 113, 117, 102, 106,
-    110, 114, 118, 103, 107, 111, 115, 119, 3, 7, 11, 15, 19, 2, 6, 10, 14, 18,
-    // electrical dot IDs in TrueGear's defination
-    0, 100,
-];
-
-static DOT_NAME_COMPACT_INDEX_MAP_CELL: OnceLock<HashMap<&'static str, usize>> = OnceLock::new();
-
-fn get_dot_name_compact_index_map() -> &'static HashMap<&'static str, usize> {
-    DOT_NAME_COMPACT_INDEX_MAP_CELL.get_or_init(|| {
-        let mut map = HashMap::new();
-        DOT_NAMES.iter().enumerate().for_each(|(i, &name)| {
-            map.insert(name, i);
-        });
-        map
-    })
+
+// uuid and name of the effects carrying the continuous dot feedback
+const CONTACT_LAYER_NAME: &str = "VRChatMsg";
+
+// prefix of the uuid and name of the effects carrying library effects
+const EFFECT_LAYER_PREFIX: &str = "VRChatFx_";
+
+// prefix of the uuid and name of the effects carrying generated patterns
+const GENERATOR_LAYER_PREFIX: &str = "VRChatGen_";
+
+// uuid and name of the effects carrying the heartbeat of the heart rate input
+const HEART_RATE_LAYER_NAME: &str = "VRChatHeartRate";
+
+// uuid and name of the effects carrying the reactions to VRChat's built-in parameters
+const LANDING_LAYER_NAME: &str = "VRChatLanding";
+const WIND_LAYER_NAME: &str = "VRChatWind";
+
+// longest pulse duration accepted from an input
+const MAX_PULSE_DURATION: Duration = Duration::from_secs(60);
+
+// Transform applied to the dots driven by the inputs, for mirrored avatar setups or a vest
+// worn differently
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct Layout {
+    // swap the left and right columns
+    pub mirror: bool,
+    // swap the front and back sides
+    pub swap_sides: bool,
+    // swap the top and bottom rows
+    pub flip_vertical: bool,
+    // swap the left and right arm electrical channels
+    pub mirror_arms: bool,
 }
 
-#[derive(clap::ValueEnum, Clone)]
+impl Layout {
+    fn transform(&self, dot: Dot) -> Dot {
+        let zone = dot.zone();
+
+        if zone.channel() == Channel::Electrical {
+            // the arms only swap with each other
+            return if self.mirror_arms {
+                Dot::new(zone.opposite(), 0, 0).expect("arms have a single dot")
+            } else {
+                dot
+            };
+        }
+
+        let zone = if self.swap_sides {
+            zone.opposite()
+        } else {
+            zone
+        };
+        let row = if self.flip_vertical {
+            NUM_ROWS - 1 - dot.row()
+        } else {
+            dot.row()
+        };
+        let column = if self.mirror {
+            NUM_COLUMNS - 1 - dot.column()
+        } else {
+            dot.column()
+        };
+
+        Dot::new(zone, row, column).expect("layout must map dots onto dots")
+    }
+
+    // Table from each compact index to the compact index it is moved to
+    pub fn table(&self) -> [usize; NUM_DOTS] {
+        std::array::from_fn(|i| self.transform(DOTS[i]).compact_index())
+    }
+}
+
+// Range of integer input values, normalized to 0.0 - 1.0
+#[derive(Clone, Copy)]
+pub struct IntRange {
+    pub min: i64,
+    pub max: i64,
+}
+
+impl Default for IntRange {
+    fn default() -> Self {
+        // VRChat Int parameters
+        Self { min: 0, max: 255 }
+    }
+}
+
+impl IntRange {
+    fn normalize(&self, value: i64) -> f32 {
+        // in f64, the difference of two i64 may not fit in an i64
+        let v = (value as f64 - self.min as f64) / (self.max as f64 - self.min as f64);
+        (v as f32).clamp(0.0, 1.0)
+    }
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FeedbackMode {
     Once,
     Continuous,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackState {
+    Playing,
+    Paused,
+    // emergency stop, inputs are ignored until re-armed
+    Stopped,
+}
+
+// impact boost level and time of the last sudden rising edge of a dot
+type Impact = (f32, Instant);
+
+// Input and output state of every dot, indexed by compact index after the layout transform
+#[derive(Debug, Clone, Copy)]
+struct DotState {
+    // own input of each dot, before combining with its groups
+    inputs: [TimedInput; NUM_DOTS],
+    intensities: [f32; NUM_DOTS],
+    // smoothed input played by each active dot
+    levels: [f32; NUM_DOTS],
+    active_states: [bool; NUM_DOTS],
+    pulse_deadlines: [Option<Instant>; NUM_DOTS],
+    last_updates: [Option<Instant>; NUM_DOTS],
+    armed_states: [bool; NUM_DOTS],
+    activation_times: [Option<Instant>; NUM_DOTS],
+    impacts: [Option<Impact>; NUM_DOTS],
+    smoothing: [SmoothingState; NUM_DOTS],
+    envelopes: [f32; NUM_DOTS],
+}
+
+impl Default for DotState {
+    fn default() -> Self {
+        Self {
+            inputs: [TimedInput::default(); NUM_DOTS],
+            intensities: [0.0; NUM_DOTS],
+            levels: [0.0; NUM_DOTS],
+            active_states: [false; NUM_DOTS],
+            pulse_deadlines: [None; NUM_DOTS],
+            last_updates: [None; NUM_DOTS],
+            armed_states: [true; NUM_DOTS],
+            activation_times: [None; NUM_DOTS],
+            impacts: [None; NUM_DOTS],
+            smoothing: [SmoothingState::default(); NUM_DOTS],
+            envelopes: [0.0; NUM_DOTS],
+        }
+    }
+}
+
+// Input level of a dot or a dot group, with the deadline of a timed pulse
+#[derive(Debug, Clone, Copy, Default)]
+struct TimedInput {
+    level: f32,
+    pulse_deadline: Option<Instant>,
+    last_update: Option<Instant>,
+}
+
+impl TimedInput {
+    fn new(level: f32, duration: Option<Duration>, now: Instant) -> Self {
+        Self {
+            level: level.max(0.0),
+            pulse_deadline: duration
+                .filter(|_| level > 0.0)
+                .and_then(|d| now.checked_add(d)),
+            last_update: Some(now),
+        }
+    }
+}
+
+// Last electrical input and pan of the arm pan inputs
+#[derive(Debug, Clone, Copy)]
+struct ArmPanState {
+    intensity: f32,
+    pan: f32,
+    // end of the last timed pulse, kept when only the pan changes
+    pulse_deadline: Option<Instant>,
+}
+
+impl Default for ArmPanState {
+    fn default() -> Self {
+        Self {
+            intensity: 0.0,
+            pan: 0.5,
+            pulse_deadline: None,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct ProtocalMapper {
-    dot_intensities: Arc<Mutex<[f32; NUM_DOTS]>>,
-    dot_active_states: Arc<Mutex<[bool; NUM_DOTS]>>,
-    dot_name_compact_index_map: &'static HashMap<&'static str, usize>,
-    pub feedback_mode: FeedbackMode,
+    dot_state: Arc<Mutex<DotState>>,
+    arm_pan: Arc<Mutex<ArmPanState>>,
+    // per configured encoded input
+    selector_states: Arc<Mutex<Vec<SelectorState>>>,
+    // per configured group
+    group_members: Arc<Vec<Vec<usize>>>,
+    group_inputs: Arc<Mutex<Vec<TimedInput>>>,
+    // dots lit by the last point input
+    point_dots: Arc<Mutex<Vec<usize>>>,
+    // dots lit by the last direction input
+    direction_dots: Arc<Mutex<Vec<usize>>>,
+    generators: Arc<Mutex<Vec<Generator>>>,
+    heart_rate: Arc<Mutex<HeartRateState>>,
+    heart_rate_members: Arc<Vec<usize>>,
+    vrchat: Arc<Mutex<VrchatState>>,
+    landing_members: Arc<Vec<usize>>,
+    wind_members: Arc<Vec<usize>>,
+    last_tick: Arc<Mutex<Option<Instant>>>,
+    config: Arc<Config>,
+    dot_mapping: Arc<DotMapping>,
+    layout_table: [usize; NUM_DOTS],
+    calibration: Calibration,
+    int_range: IntRange,
+    settings: Arc<Mutex<RuntimeSettings>>,
+    playback_state: Arc<Mutex<PlaybackState>>,
+    gestures: Arc<Mutex<[i32; 2]>>,
+    effect_library: Arc<EffectLibrary>,
+    effect_cooldowns: Arc<Mutex<HashMap<String, Instant>>>,
+    playing_effects: Arc<Mutex<Vec<PlayingEffect>>>,
+    pending_effects: Arc<Mutex<Vec<true_gear_message::Effect>>>,
+    effect_notify: Arc<Notify>,
 }
 
 impl Default for ProtocalMapper {
     fn default() -> Self {
         Self {
-            dot_intensities: Arc::new(Mutex::new([0.0; NUM_DOTS])),
-            dot_active_states: Arc::new(Mutex::new([false; NUM_DOTS])),
-            dot_name_compact_index_map: get_dot_name_compact_index_map(),
-            feedback_mode: FeedbackMode::Continuous,
+            dot_state: Arc::new(Mutex::new(DotState::default())),
+            arm_pan: Arc::new(Mutex::new(ArmPanState::default())),
+            selector_states: Arc::new(Mutex::new(Vec::new())),
+            group_members: Arc::new(Vec::new()),
+            group_inputs: Arc::new(Mutex::new(Vec::new())),
+            point_dots: Arc::new(Mutex::new(Vec::new())),
+            direction_dots: Arc::new(Mutex::new(Vec::new())),
+            generators: Arc::new(Mutex::new(generator::generators())),
+            heart_rate: Arc::new(Mutex::new(HeartRateState::default())),
+            heart_rate_members: Arc::new(Vec::new()),
+            vrchat: Arc::new(Mutex::new(VrchatState::default())),
+            landing_members: Arc::new(Vec::new()),
+            wind_members: Arc::new(Vec::new()),
+            last_tick: Arc::new(Mutex::new(None)),
+            config: Arc::new(Config::default()),
+            dot_mapping: Arc::new(DotMapping::builtin()),
+            layout_table: Layout::default().table(),
+            calibration: Calibration::default(),
+            int_range: IntRange::default(),
+            settings: Arc::new(Mutex::new(RuntimeSettings {
+                shake_intensity: 50,
+                electrical_intensity: [50; NUM_ELECTRICAL],
+                electrical_interval: [10; NUM_ELECTRICAL],
+                feedback_mode: FeedbackMode::Continuous,
+            })),
+            playback_state: Arc::new(Mutex::new(PlaybackState::Playing)),
+            gestures: Arc::new(Mutex::new([0; 2])),
+            effect_library: Arc::new(EffectLibrary::default()),
+            effect_cooldowns: Arc::new(Mutex::new(HashMap::new())),
+            playing_effects: Arc::new(Mutex::new(Vec::new())),
+            pending_effects: Arc::new(Mutex::new(Vec::new())),
+            effect_notify: Arc::new(Notify::new()),
         }
     }
 }
 
 impl ProtocalMapper {
-    pub fn new(feedback_mode: FeedbackMode) -> Self {
+    pub fn new(
+        settings: RuntimeSettings,
+        dot_mapping: DotMapping,
+        int_range: IntRange,
+        config: Config,
+        effect_library: EffectLibrary,
+        calibration: Calibration,
+    ) -> Self {
         Self {
-            dot_intensities: Arc::new(Mutex::new([0.0; NUM_DOTS])),
-            dot_active_states: Arc::new(Mutex::new([false; NUM_DOTS])),
-            dot_name_compact_index_map: get_dot_name_compact_index_map(),
-            feedback_mode,
+            dot_state: Arc::new(Mutex::new(DotState::default())),
+            arm_pan: Arc::new(Mutex::new(ArmPanState::default())),
+            selector_states: Arc::new(Mutex::new(vec![
+                SelectorState::default();
+                config.encoded_inputs.len()
+            ])),
+            group_members: Arc::new(config.groups.iter().map(|g| g.members()).collect()),
+            group_inputs: Arc::new(Mutex::new(vec![TimedInput::default(); config.groups.len()])),
+            point_dots: Arc::new(Mutex::new(Vec::new())),
+            direction_dots: Arc::new(Mutex::new(Vec::new())),
+            generators: Arc::new(Mutex::new(generator::generators())),
+            heart_rate: Arc::new(Mutex::new(HeartRateState::default())),
+            heart_rate_members: Arc::new(
+                config
+                    .heart_rate
+                    .as_ref()
+                    .map(|h| h.members())
+                    .unwrap_or_default(),
+            ),
+            vrchat: Arc::new(Mutex::new(VrchatState::default())),
+            landing_members: Arc::new(
+                config
+                    .vrchat
+                    .landing
+                    .as_ref()
+                    .map(|l| l.members())
+                    .unwrap_or_default(),
+            ),
+            wind_members: Arc::new(
+                config
+                    .vrchat
+                    .wind
+                    .as_ref()
+                    .map(|w| w.members())
+                    .unwrap_or_default(),
+            ),
+            last_tick: Arc::new(Mutex::new(None)),
+            layout_table: config.layout.table(),
+            calibration,
+            config: Arc::new(config),
+            dot_mapping: Arc::new(dot_mapping),
+            int_range,
+            settings: Arc::new(Mutex::new(settings)),
+            playback_state: Arc::new(Mutex::new(PlaybackState::Playing)),
+            gestures: Arc::new(Mutex::new([0; 2])),
+            effect_library: Arc::new(effect_library),
+            effect_cooldowns: Arc::new(Mutex::new(HashMap::new())),
+            playing_effects: Arc::new(Mutex::new(Vec::new())),
+            pending_effects: Arc::new(Mutex::new(Vec::new())),
+            effect_notify: Arc::new(Notify::new()),
         }
     }
 
-    fn extract_intensity(msg: &OscMessage) -> Option<f32> {
-        if msg.args.is_empty() {
-            None
-        } else {
-            match &msg.args[0] {
-                OscType::Float(f) => Some(*f),
-                OscType::Double(f) => Some(*f as f32),
-                OscType::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
-                _ => None,
+    fn extract_value(&self, arg: &OscType) -> Option<f32> {
+        match arg {
+            OscType::Float(f) => Some(*f),
+            OscType::Double(f) => Some(*f as f32),
+            OscType::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+            OscType::Int(i) => Some(self.int_range.normalize(*i as i64)),
+            OscType::Long(l) => Some(self.int_range.normalize(*l)),
+            OscType::String(s) => s.trim().parse::<f32>().ok(),
+            _ => None,
+        }
+    }
+
+    fn extract_duration(arg: &OscType) -> Option<Duration> {
+        let ms = match arg {
+            OscType::Float(f) => *f as f64,
+            OscType::Double(f) => *f,
+            OscType::Int(i) => *i as f64,
+            OscType::Long(l) => *l as f64,
+            OscType::String(s) => s.trim().parse::<f64>().ok()?,
+            _ => return None,
+        };
+        if !ms.is_finite() || ms <= 0.0 {
+            return None;
+        }
+        // cap before converting, huge values do not fit in a Duration
+        let secs = (ms / 1000.0).min(MAX_PULSE_DURATION.as_secs_f64());
+        Duration::try_from_secs_f64(secs).ok()
+    }
+
+    // Intensity from the first argument, optional pulse duration in ms from the second argument
+    fn extract_intensity(&self, msg: &OscMessage) -> Option<(f32, Option<Duration>)> {
+        let intensity = self.extract_value(msg.args.first()?)?;
+        let duration = msg.args.get(1).and_then(Self::extract_duration);
+        Some((intensity, duration))
+    }
+
+    // Device intensity of a dot at an envelope level, through the channel's response curve
+    fn scale_intensity(channel: &ChannelConfig, base: u16, level: f32) -> f32 {
+        (base as f32 * channel.shape(level)).clamp(0.0, MAX_INTENSITY as f32)
+    }
+
+    // Handle pause / resume / emergency stop commands, from their addresses or from hand gestures.
+    // Returns whether the message was a command message.
+    async fn consume_command_message(&mut self, msg: &OscMessage) -> bool {
+        if let Some(command) = Command::from_address(&msg.addr) {
+            if Command::is_triggered(msg) {
+                self.run_manual_command(command).await;
             }
+            return true;
         }
+
+        // gestures may also drive other mappings
+        self.observe_gestures(msg).await;
+        false
     }
 
-    fn scale_intensity(base: u16, factor: f32) -> u16 {
-        let v = (base as f32 * factor).round();
-        v.clamp(0.0, 150.0) as u16
+    // Follow the hand gestures, and fire the gesture commands and gesture effects of a newly
+    // made combination
+    async fn observe_gestures(&mut self, msg: &OscMessage) {
+        let (previous_gestures, gestures) = {
+            let mut gestures = self.gestures.lock().await;
+            let previous_gestures = *gestures;
+            if !GestureCommand::update_gestures(&mut gestures, msg) {
+                return;
+            }
+            (previous_gestures, *gestures)
+        };
+
+        // fire once when the combination is entered
+        let commands: Vec<Command> = self
+            .config
+            .gesture_commands
+            .iter()
+            .filter(|g| g.matches(&gestures) && !g.matches(&previous_gestures))
+            .map(|g| g.command)
+            .collect();
+        for command in commands {
+            self.run_manual_command(command).await;
+        }
+
+        let effects = self
+            .config
+            .vrchat
+            .gesture_effects(&previous_gestures, &gestures);
+        for name in effects {
+            self.play_effect(&msg.addr, &name, None).await;
+        }
     }
 
-    async fn consume_osc_message(self: &mut ProtocalMapper, msg: &OscMessage) {
-        let Some(dot_key) = msg.addr.rsplit('/').next() else {
-            return;
+    // Run a command from its address or a gesture
+    async fn run_manual_command(&mut self, command: Command) {
+        if matches!(command, Command::Pause | Command::Resume) {
+            self.vrchat.lock().await.clear_auto_pause();
+        }
+        self.run_command(command).await;
+    }
+
+    pub async fn run_command(&mut self, command: Command) {
+        let mut playback_state = self.playback_state.lock().await;
+        match (command, *playback_state) {
+            (Command::Pause, PlaybackState::Playing) => {
+                *playback_state = PlaybackState::Paused;
+                tracing::info!("Haptics paused");
+            }
+            (Command::Resume, PlaybackState::Paused) => {
+                *playback_state = PlaybackState::Playing;
+                tracing::info!("Haptics resumed");
+            }
+            (Command::Resume, PlaybackState::Stopped) => {
+                tracing::warn!("Haptics are emergency stopped, re-arm to resume");
+            }
+            (Command::EmergencyStop, _) => {
+                *playback_state = PlaybackState::Stopped;
+                drop(playback_state);
+                self.reset_inputs().await;
+                self.dot_state.lock().await.envelopes = [0.0; NUM_DOTS];
+                self.playing_effects.lock().await.clear();
+                for generator in self.generators.lock().await.iter_mut() {
+                    generator.stop();
+                }
+                self.pending_effects
+                    .lock()
+                    .await
+                    .push(Self::build_stop_effect());
+                self.effect_notify.notify_one();
+                tracing::warn!("Emergency stop, inputs are ignored until re-armed");
+            }
+            (Command::Rearm, PlaybackState::Stopped) => {
+                *playback_state = PlaybackState::Playing;
+                tracing::info!("Haptics re-armed");
+            }
+            _ => {}
+        }
+    }
+
+    // Resolves when effects should be sent without waiting for the next tick
+    pub async fn effects_pending(&self) {
+        self.effect_notify.notified().await;
+    }
+
+    pub async fn take_pending_effects(&self) -> Vec<true_gear_message::Effect> {
+        std::mem::take(&mut *self.pending_effects.lock().await)
+    }
+
+    // Play library effects from their triggers, returns whether the message was an effect trigger
+    async fn consume_effect_trigger(&mut self, msg: &OscMessage) -> bool {
+        let triggers: Vec<&EffectTrigger> = self
+            .config
+            .effect_triggers
+            .iter()
+            .filter(|t| t.address == msg.addr)
+            .collect();
+        if triggers.is_empty() && !EffectLibrary::is_builtin_trigger(&msg.addr) {
+            return false;
+        }
+
+        if msg.addr == STOP_ADDRESS {
+            if let Some(OscType::String(name)) = msg.args.first() {
+                self.stop_effect(name).await;
+            }
+            return true;
+        }
+
+        let mut plays: Vec<(String, Option<u64>)> = Vec::new();
+        if triggers.is_empty() {
+            let effect = EffectLibrary::builtin_trigger_effect(&msg.addr);
+            if let Some(name) = self.select_effect(effect, msg) {
+                plays.push((name, None));
+            }
+        }
+        for trigger in triggers {
+            if let Some(name) = self.select_effect(trigger.effect.as_deref(), msg) {
+                plays.push((name, trigger.cooldown_ms));
+            }
+        }
+
+        for (name, cooldown_ms) in plays {
+            self.play_effect(&msg.addr, &name, cooldown_ms).await;
+        }
+
+        true
+    }
+
+    // React to VRChat's built-in avatar parameters
+    async fn observe_vrchat_parameter(&mut self, msg: &OscMessage) {
+        let is_playing = *self.playback_state.lock().await == PlaybackState::Playing;
+        let reactions =
+            self.vrchat
+                .lock()
+                .await
+                .update(&self.config.vrchat, msg, is_playing, Instant::now());
+
+        for reaction in reactions {
+            match reaction {
+                Reaction::Landed => self.effect_notify.notify_one(),
+                Reaction::Pause => self.run_command(Command::Pause).await,
+                Reaction::Resume => self.run_command(Command::Resume).await,
+            }
+        }
+    }
+
+    // Start / stop generators and set their parameters, returns whether the message was for a
+    // generator
+    async fn consume_generator_message(&mut self, msg: &OscMessage) -> bool {
+        let Some((kind, param)) = generator::parse_address(&msg.addr) else {
+            return false;
         };
-        let Some(dot_index_compact) = self.dot_name_compact_index_map.get(dot_key) else {
-            return;
+        let is_playing = *self.playback_state.lock().await == PlaybackState::Playing;
+
+        let mut generators = self.generators.lock().await;
+        let Some(generator) = generators.iter_mut().find(|g| g.kind == kind) else {
+            return true;
+        };
+
+        let Some(param) = param else {
+            if !Command::is_triggered(msg) {
+                generator.stop();
+                tracing::debug!("Stopped generator {}", kind.name());
+            } else if is_playing && !generator.is_running() {
+                generator.start(Instant::now());
+                self.effect_notify.notify_one();
+                tracing::debug!("Started generator {}", kind.name());
+            }
+            return true;
+        };
+
+        let is_valid = match (param, msg.args.first()) {
+            ("side", Some(arg)) => spatial::extract_side(arg)
+                .map(|side| generator.params.side = side)
+                .is_some(),
+            ("intensity", Some(arg)) => self
+                .extract_value(arg)
+                .is_some_and(|value| generator.params.set(param, value)),
+            (_, Some(arg)) => spatial::extract_coordinate(arg)
+                .is_some_and(|value| generator.params.set(param, value)),
+            (_, None) => false,
+        };
+        if !is_valid {
+            tracing::warn!("Invalid generator parameter {}", msg.addr);
+        }
+
+        true
+    }
+
+    // A trigger with a fixed effect plays it on a true / non-zero value,
+    // otherwise the value selects the effect by name (String) or by index (Int)
+    fn select_effect(&self, effect: Option<&str>, msg: &OscMessage) -> Option<String> {
+        if let Some(name) = effect {
+            return Command::is_triggered(msg).then(|| name.to_string());
+        }
+
+        let selected = match msg.args.first()? {
+            OscType::String(name) => self.effect_library.get(name),
+            OscType::Int(i) => self.effect_library.get_by_index(*i as i64),
+            OscType::Long(l) => self.effect_library.get_by_index(*l),
+            _ => None,
         };
+        selected.map(|(name, _)| name.to_string())
+    }
 
-        tracing::debug!("Matched OSC message to dot key {}", dot_key);
+    async fn play_effect(&mut self, trigger_addr: &str, name: &str, cooldown_ms: Option<u64>) {
+        if *self.playback_state.lock().await != PlaybackState::Playing {
+            return;
+        }
 
-        let Some(intensity) = Self::extract_intensity(msg) else {
+        let Some((_, effect)) = self.effect_library.get(name) else {
+            tracing::warn!("Effect {} from {} not found in library", name, trigger_addr);
             return;
         };
+
+        let now = Instant::now();
+        let cooldown = Duration::from_millis(cooldown_ms.unwrap_or(self.config.effect_cooldown_ms));
         {
-            self.dot_intensities.lock().await[*dot_index_compact] = intensity;
+            let mut effect_cooldowns = self.effect_cooldowns.lock().await;
+            let key = format!("{} {}", trigger_addr, name);
+            if effect_cooldowns
+                .get(&key)
+                .is_some_and(|last| now - *last < cooldown)
+            {
+                tracing::debug!("Effect {} from {} is cooling down", name, trigger_addr);
+                return;
+            }
+            effect_cooldowns.insert(key, now);
+        }
+
+        tracing::debug!("Playing effect {} from {}", name, trigger_addr);
+        {
+            // restart the effect if it is already playing
+            let mut playing_effects = self.playing_effects.lock().await;
+            playing_effects.retain(|p| p.name != name);
+            playing_effects.push(PlayingEffect::new(name.to_string(), effect.clone(), now));
+        }
+        self.effect_notify.notify_one();
+    }
+
+    async fn stop_effect(&mut self, name: &str) {
+        self.playing_effects.lock().await.retain(|p| p.name != name);
+        tracing::debug!("Stopped effect {}", name);
+    }
+
+    // An effect silencing every dot, overriding anything currently playing
+    fn build_stop_effect() -> true_gear_message::Effect {
+        let (shake_dots, electrical_dots) = DOTS.split_at(NUM_SHAKES);
+
+        true_gear_message::Effect {
+            uuid: "VRChatStop".to_string(),
+            name: "VRChatStop".to_string(),
+            keep: false,
+            priority: u16::MAX,
+            tracks: vec![
+                Mixer::build_track(Channel::Shake, 0, 0, 0, shake_dots),
+                Mixer::build_track(Channel::Electrical, 0, 0, 0, electrical_dots),
+            ],
+        }
+    }
+
+    // Apply runtime control messages, returns whether the message was a control message
+    async fn consume_control_message(&mut self, msg: &OscMessage) -> bool {
+        let matched = RuntimeSettings::match_settings(&msg.addr, &self.config.control_bindings);
+        if matched.is_empty() {
+            return false;
+        }
+
+        let mut settings = self.settings.lock().await;
+        let previous_mode = settings.feedback_mode;
+        for setting in matched {
+            if !settings.apply(setting, msg) {
+                tracing::warn!("Invalid value for {:?} from {}", setting, msg.addr);
+            }
+        }
+
+        if settings.feedback_mode != previous_mode {
+            drop(settings);
+            self.reset_inputs().await;
+        }
+
+        true
+    }
+
+    // Forget all dot inputs, e.g. when the feedback mode changes
+    async fn reset_inputs(&mut self) {
+        // the envelopes fade out from where they are
+        let mut dot_state = self.dot_state.lock().await;
+        *dot_state = DotState {
+            envelopes: dot_state.envelopes,
+            ..DotState::default()
+        };
+        drop(dot_state);
+
+        let mut arm_pan = self.arm_pan.lock().await;
+        *arm_pan = ArmPanState {
+            pan: arm_pan.pan,
+            ..ArmPanState::default()
+        };
+        drop(arm_pan);
+
+        let mut selector_states = self.selector_states.lock().await;
+        selector_states.fill(SelectorState::default());
+
+        let mut group_inputs = self.group_inputs.lock().await;
+        group_inputs.fill(TimedInput::default());
+
+        self.point_dots.lock().await.clear();
+        self.direction_dots.lock().await.clear();
+        self.heart_rate.lock().await.reset();
+    }
+
+    async fn consume_osc_message(self: &mut ProtocalMapper, msg: &OscMessage) {
+        if self.consume_command_message(msg).await {
+            return;
+        }
+
+        if self.consume_control_message(msg).await {
+            return;
+        }
+
+        if self.consume_effect_trigger(msg).await {
+            return;
+        }
+
+        if self.consume_generator_message(msg).await {
+            return;
+        }
+
+        // built-in parameters may also drive other mappings
+        self.observe_vrchat_parameter(msg).await;
+
+        if *self.playback_state.lock().await == PlaybackState::Stopped {
+            return;
+        }
+
+        if self.consume_arm_pan(msg).await {
+            return;
+        }
+
+        if self.consume_encoded_input(msg).await {
+            return;
+        }
+
+        if self.consume_group_input(msg).await {
+            return;
+        }
+
+        if self.consume_point_input(msg).await {
+            return;
+        }
+
+        if self.consume_direction_input(msg).await {
+            return;
         }
 
-        tracing::debug!("Set intensity for {} to {}", dot_key, intensity);
+        if self.consume_heart_rate(msg).await {
+            return;
+        }
 
-        let is_active = intensity > 0.0;
+        let matched = self.dot_mapping.lookup(&msg.addr);
+        if matched.is_empty() {
+            return;
+        }
+
+        let Some((intensity, duration)) = self.extract_intensity(msg) else {
+            return;
+        };
 
-        self.dot_active_states.lock().await[*dot_index_compact] = is_active;
-        tracing::debug!("Set active state for {} to {}", dot_key, is_active);
+        for (dot_key, targets) in matched {
+            tracing::debug!("Matched OSC message {} to {}", msg.addr, dot_key);
+
+            let inputs: Vec<(usize, f32)> = targets
+                .iter()
+                .map(|target| (target.compact_index, intensity * target.gain))
+                .collect();
+            self.set_dot_inputs(dot_key, &inputs, duration).await;
+
+            tracing::debug!("Set intensity for {} to {}", dot_key, intensity);
+        }
+    }
+
+    // Drive both arms from one electrical input, distributed by the pan input.
+    // Returns whether the message was an arm pan input.
+    async fn consume_arm_pan(&mut self, msg: &OscMessage) -> bool {
+        let Some(arm_pan) = &self.config.arm_pan else {
+            return false;
+        };
+        let is_input = msg.addr == arm_pan.address;
+        let is_pan = msg.addr == arm_pan.pan_address;
+        if !is_input && !is_pan {
+            return false;
+        }
+
+        let Some((value, duration)) = self.extract_intensity(msg) else {
+            return true;
+        };
+
+        let now = Instant::now();
+        let (intensity, pan, remaining) = {
+            let mut state = self.arm_pan.lock().await;
+            if is_input {
+                state.intensity = value;
+                state.pulse_deadline = duration.and_then(|d| now.checked_add(d));
+            } else {
+                state.pan = value.clamp(0.0, 1.0);
+                // a pulse that has already ended is not replayed by a later pan
+                if state.pulse_deadline.is_some_and(|d| d <= now) {
+                    state.intensity = 0.0;
+                    state.pulse_deadline = None;
+                }
+            }
+            let remaining = state.pulse_deadline.map(|d| d - now);
+            (state.intensity, state.pan, remaining)
+        };
+
+        // both arms play at full input in the center, fading out one side towards the edges
+        let left = ((1.0 - pan) * 2.0).min(1.0);
+        let right = (pan * 2.0).min(1.0);
+        let inputs = [
+            (Dot::LEFT_ARM.compact_index(), intensity * left),
+            (Dot::RIGHT_ARM.compact_index(), intensity * right),
+        ];
+        self.set_dot_inputs(&msg.addr, &inputs, remaining).await;
+
+        true
+    }
+
+    // Decode compact input encodings into dot inputs, returns whether the message was one
+    async fn consume_encoded_input(&mut self, msg: &OscMessage) -> bool {
+        let Some(n) = self
+            .config
+            .encoded_inputs
+            .iter()
+            .position(|input| input.matches(&msg.addr))
+        else {
+            return false;
+        };
+        let Some(arg) = msg.args.first() else {
+            return true;
+        };
+
+        let value = self.extract_value(arg);
+        let inputs = {
+            let mut selector_states = self.selector_states.lock().await;
+            self.config.encoded_inputs[n].decode(&mut selector_states[n], &msg.addr, arg, value)
+        };
+        let Some(inputs) = inputs else {
+            tracing::warn!("Invalid value for encoded input {}", msg.addr);
+            return true;
+        };
+
+        self.set_dot_inputs(&msg.addr, &inputs, None).await;
+        tracing::debug!("Decoded {} into {} dot inputs", msg.addr, inputs.len());
+
+        true
+    }
+
+    // Set the input of a dot group, returns whether the message was a group input
+    async fn consume_group_input(&mut self, msg: &OscMessage) -> bool {
+        let Some(n) = self
+            .config
+            .groups
+            .iter()
+            .position(|group| group.addresses().contains(&msg.addr))
+        else {
+            return false;
+        };
+        let Some((intensity, duration)) = self.extract_intensity(msg) else {
+            return true;
+        };
+
+        let now = Instant::now();
+        let feedback_mode = self.settings.lock().await.feedback_mode;
+        let mut group_inputs = self.group_inputs.lock().await;
+        group_inputs[n] = TimedInput::new(intensity, duration, now);
+
+        // member dots switch on and off like they do for their own inputs
+        let mut dot_state = self.dot_state.lock().await;
+        for &member in &self.group_members[n] {
+            let i = self.layout_table[member];
+            let input = self.combined_input(&dot_state, &group_inputs, i);
+            self.update_dot(&mut dot_state, i, input, duration, feedback_mode, now);
+        }
+        tracing::debug!(
+            "Set intensity for group {} to {}",
+            self.config.groups[n].name,
+            intensity
+        );
+
+        true
+    }
+
+    // Spread a hit at a position over the dots around it, returns whether the message was a
+    // point input
+    async fn consume_point_input(&mut self, msg: &OscMessage) -> bool {
+        if msg.addr != POINT_ADDRESS {
+            return false;
+        }
+
+        let args = &msg.args;
+        let zone = args.first().and_then(spatial::extract_side);
+        let x = args.get(1).and_then(spatial::extract_coordinate);
+        let y = args.get(2).and_then(spatial::extract_coordinate);
+        let intensity = args.get(3).and_then(|arg| self.extract_value(arg));
+        let (Some(zone), Some(x), Some(y), Some(intensity)) = (zone, x, y, intensity) else {
+            tracing::warn!("Invalid point input, expected side, x, y and intensity");
+            return true;
+        };
+        let duration = args.get(4).and_then(Self::extract_duration);
+
+        let spread = self.config.point.falloff.spread(zone, x, y);
+        self.set_spread_inputs(&msg.addr, &self.point_dots, &spread, intensity, duration)
+            .await;
+        tracing::debug!(
+            "Spread point input at ({}, {}) of {:?} over {} dots",
+            x,
+            y,
+            zone,
+            spread.len()
+        );
+
+        true
+    }
+
+    // Light the dots of a direction around the torso, returns whether the message was a
+    // direction input
+    async fn consume_direction_input(&mut self, msg: &OscMessage) -> bool {
+        if msg.addr != DIRECTION_ADDRESS {
+            return false;
+        }
+
+        let args = &msg.args;
+        let angle = args.first().and_then(spatial::extract_coordinate);
+        let intensity = args.get(1).and_then(|arg| self.extract_value(arg));
+        let (Some(angle), Some(intensity)) = (angle, intensity) else {
+            tracing::warn!("Invalid direction input, expected angle and intensity");
+            return true;
+        };
+        let height = args.get(2).and_then(spatial::extract_coordinate);
+
+        let spread = self.config.direction.spread(angle, height);
+        self.set_spread_inputs(&msg.addr, &self.direction_dots, &spread, intensity, None)
+            .await;
+        tracing::debug!(
+            "Spread direction input at {} degrees over {} dots",
+            angle,
+            spread.len()
+        );
+
+        true
+    }
+
+    // Follow the heart rate input, returns whether the message was one
+    async fn consume_heart_rate(&mut self, msg: &OscMessage) -> bool {
+        let Some(heart_rate) = &self.config.heart_rate else {
+            return false;
+        };
+        if msg.addr != heart_rate.address {
+            return false;
+        }
+
+        let Some(bpm) = msg.args.first().and_then(|arg| heart_rate.extract_bpm(arg)) else {
+            tracing::warn!("Invalid heart rate from {}", msg.addr);
+            return true;
+        };
+        let started = self
+            .heart_rate
+            .lock()
+            .await
+            .set_bpm(heart_rate, bpm, Instant::now());
+        if started {
+            self.effect_notify.notify_one();
+        }
+        tracing::debug!("Heart rate {} BPM", bpm);
+
+        true
+    }
+
+    // Feed an input spread over weighted dots, replacing the dots lit by the previous input of
+    // the same source
+    async fn set_spread_inputs(
+        &self,
+        key: &str,
+        lit_dots: &Mutex<Vec<usize>>,
+        spread: &[(usize, f32)],
+        intensity: f32,
+        duration: Option<Duration>,
+    ) {
+        let mut inputs: Vec<(usize, f32)> = {
+            // switch off the dots that are not lit anymore
+            let mut lit_dots = lit_dots.lock().await;
+            let released = lit_dots
+                .iter()
+                .filter(|i| !spread.iter().any(|(j, _)| j == *i))
+                .map(|&i| (i, 0.0))
+                .collect();
+            *lit_dots = spread.iter().map(|&(i, _)| i).collect();
+            released
+        };
+        inputs.extend(spread.iter().map(|&(i, weight)| (i, intensity * weight)));
+
+        self.set_dot_inputs(key, &inputs, duration).await;
+    }
+
+    // Feed input values to dots, given by compact index before the layout transform
+    async fn set_dot_inputs(&self, key: &str, inputs: &[(usize, f32)], duration: Option<Duration>) {
+        let now = Instant::now();
+        let feedback_mode = self.settings.lock().await.feedback_mode;
+
+        let group_inputs = self.group_inputs.lock().await;
+        let mut dot_state = self.dot_state.lock().await;
+        for &(compact_index, dot_intensity) in inputs {
+            let i = self.layout_table[compact_index];
+            dot_state.inputs[i] = TimedInput::new(dot_intensity, duration, now);
+            let input = self.combined_input(&dot_state, &group_inputs, i);
+            if self.update_dot(&mut dot_state, i, input, duration, feedback_mode, now) {
+                tracing::debug!("Triggered pulse for {}", key);
+            }
+        }
+    }
+
+    // Highest of the own input of a dot and the inputs of its groups
+    fn combined_input(&self, dot_state: &DotState, group_inputs: &[TimedInput], i: usize) -> f32 {
+        self.group_members
+            .iter()
+            .zip(group_inputs)
+            .filter(|(members, _)| members.iter().any(|&m| self.layout_table[m] == i))
+            .fold(dot_state.inputs[i].level, |level, (_, group)| {
+                level.max(group.level)
+            })
+    }
+
+    // Switch a dot on or off for a new combined input, returns whether a Once pulse was fired
+    fn update_dot(
+        &self,
+        dot_state: &mut DotState,
+        i: usize,
+        input: f32,
+        duration: Option<Duration>,
+        feedback_mode: FeedbackMode,
+        now: Instant,
+    ) -> bool {
+        let channel = self.config.channel(DOTS[i].channel());
+        let previous_update = dot_state.last_updates[i].replace(now);
+
+        match feedback_mode {
+            FeedbackMode::Continuous => {
+                // hysteresis: switch on above the activation threshold, and only switch off
+                // again below the deactivation threshold
+                let is_active = if dot_state.active_states[i] {
+                    input > channel.off_threshold()
+                } else {
+                    input > channel.activation_threshold
+                };
+
+                if is_active {
+                    if let Some(impact_boost) = &channel.impact_boost {
+                        let (previous, elapsed) = if dot_state.active_states[i] {
+                            (dot_state.intensities[i], previous_update.map(|t| now - t))
+                        } else {
+                            (0.0, None)
+                        };
+                        if let Some(boost) = impact_boost.boost(input - previous, elapsed) {
+                            dot_state.impacts[i] = Some((boost, now));
+                        }
+                    }
+
+                    if !dot_state.active_states[i] {
+                        dot_state.activation_times[i] = Some(now);
+                        Smoothing::reset(&mut dot_state.smoothing[i]);
+                    }
+                    // smooth over the time since the previous input sample
+                    let dt = previous_update.map_or(Duration::ZERO, |t| now - t);
+                    dot_state.levels[i] =
+                        channel
+                            .smoothing
+                            .update(&mut dot_state.smoothing[i], input, dt);
+                    dot_state.intensities[i] = input;
+                    dot_state.active_states[i] = true;
+                    // timed inputs are released through their own deadlines
+                    dot_state.pulse_deadlines[i] = None;
+                    return false;
+                }
+
+                // hold a dot switched off too early at its last intensity, until the end of
+                // its minimum on-time
+                let min_on_end = dot_state.activation_times[i]
+                    .and_then(|t| t.checked_add(Duration::from_millis(channel.min_on_ms)))
+                    .filter(|&end| dot_state.active_states[i] && end > now);
+                if let Some(end) = min_on_end {
+                    dot_state.pulse_deadlines[i] = Some(end);
+                    return false;
+                }
+
+                dot_state.intensities[i] = input;
+                dot_state.active_states[i] = false;
+                dot_state.pulse_deadlines[i] = None;
+                false
+            }
+            FeedbackMode::Once => {
+                let is_above_threshold = input > self.config.once.rearm_threshold;
+
+                if dot_state.armed_states[i] && is_above_threshold {
+                    // rising edge, fire a pulse and wait for the input to drop again
+                    let pulse_duration =
+                        duration.unwrap_or(Duration::from_millis(self.config.once.pulse_ms));
+                    dot_state.intensities[i] = input;
+                    dot_state.levels[i] = input;
+                    dot_state.active_states[i] = true;
+                    dot_state.pulse_deadlines[i] = now.checked_add(pulse_duration);
+                    dot_state.armed_states[i] = false;
+                    return true;
+                }
+                if !is_above_threshold {
+                    dot_state.armed_states[i] = true;
+                }
+                false
+            }
+        }
     }
 
     pub fn consume_osc_packet<'a>(
@@ -171,109 +1138,574 @@ impl ProtocalMapper {
         })
     }
 
-    pub async fn build_effect(
-        &mut self,
-        shake_intensity: u16,
-        electrical_intensity: u16,
-        electrical_interval: u8,
-    ) -> Option<true_gear_message::Effect> {
-        // Lock the mutex to access the array
-        let percentage = self.dot_intensities.lock().await;
-        let max_shake_intensity = percentage[..40].iter().cloned().fold(0 as f32, f32::max);
-        let max_electrical_intensity = percentage[40..].iter().cloned().fold(0 as f32, f32::max);
-
-        drop(percentage);
-
-        let shake_track = true_gear_message::Track {
-            action_type: true_gear_message::ActionType::Shake,
-            intensity_mode: true_gear_message::IntensityMode::Const,
-            stop_name: "".to_string(),
-            start_intensity: Self::scale_intensity(shake_intensity, max_shake_intensity),
-            end_intensity: Self::scale_intensity(shake_intensity, max_shake_intensity),
-            start_time: 0,
-            end_time: 150,
-            interval: 0,
-            once: false,
-            // index: shake_index.into_iter().collect(),
-            index: self
-                .dot_active_states
-                .lock()
-                .await
-                .iter()
-                .enumerate()
-                .filter_map(|(i, &active)| {
-                    if active && i < NUM_SHAKES {
-                        Some(DOT_IDS[i])
-                    } else {
-                        None
-                    }
-                })
-                .collect(),
-        };
-
-        let electrical_track = true_gear_message::Track {
-            action_type: true_gear_message::ActionType::Electrical,
-            intensity_mode: true_gear_message::IntensityMode::Const,
-            stop_name: "".to_string(),
-            start_intensity: Self::scale_intensity(electrical_intensity, max_electrical_intensity),
-            end_intensity: Self::scale_intensity(electrical_intensity, max_electrical_intensity),
-            start_time: 0,
-            end_time: 150,
-            interval: electrical_interval,
-            once: false,
-            // index: electrical_index.into_iter().collect(),
-            index: self
-                .dot_active_states
-                .lock()
-                .await
-                .iter()
-                .enumerate()
-                .filter_map(|(i, &active)| {
-                    if active && i >= NUM_SHAKES {
-                        Some(DOT_IDS[i])
-                    } else {
-                        None
-                    }
-                })
-                .collect(),
+    // Move an envelope level towards its target, limited by the channel's attack / release time
+    fn envelope_step(channel: &ChannelConfig, level: f32, target: f32, dt: Duration) -> f32 {
+        let ramp_ms = if target > level {
+            channel.attack_ms
+        } else {
+            channel.release_ms
         };
+        if ramp_ms == 0 {
+            return target;
+        }
 
-        let mut effect = true_gear_message::Effect {
-            uuid: "VRChatMsg".to_string(),
-            name: "VRChatMsg".to_string(),
-            keep: false,
-            priority: 0,
-            tracks: Vec::new(),
+        let max_delta = dt.as_secs_f32() * 1000.0 / ramp_ms as f32;
+        if target > level {
+            (level + max_delta).min(target)
+        } else {
+            (level - max_delta).max(target)
+        }
+    }
+
+    // Switch off dots whose timed pulse has ended, or whose input has gone silent
+    async fn release_inputs(&mut self) {
+        let now = Instant::now();
+        let stale_timeout =
+            Some(Duration::from_millis(self.config.stale_timeout_ms)).filter(|t| !t.is_zero());
+        let feedback_mode = self.settings.lock().await.feedback_mode;
+
+        // inputs that ended, the dots they drive take their remaining input
+        let is_released = |input: &TimedInput, name: &str| {
+            if input.pulse_deadline.is_some_and(|d| d <= now) {
+                return true;
+            }
+            if let Some(timeout) = stale_timeout
+                && input.level > 0.0
+                && input.last_update.is_some_and(|t| now - t >= timeout)
+            {
+                tracing::warn!(
+                    "No input for {} in {} ms, switching it off",
+                    name,
+                    timeout.as_millis()
+                );
+                return true;
+            }
+            false
         };
 
-        // only add non-empty tracks
-        if !shake_track.index.is_empty() {
-            effect.tracks.push(shake_track);
+        let mut group_inputs = self.group_inputs.lock().await;
+        let mut dot_state = self.dot_state.lock().await;
+        let mut released = [false; NUM_DOTS];
+        for (i, name) in DOT_NAMES.iter().enumerate() {
+            if is_released(&dot_state.inputs[i], name) {
+                dot_state.inputs[i] = TimedInput::default();
+                released[i] = true;
+            }
         }
-        if !electrical_track.index.is_empty() {
-            effect.tracks.push(electrical_track);
+        for (n, group) in self.config.groups.iter().enumerate() {
+            if is_released(&group_inputs[n], &format!("group {}", group.name)) {
+                group_inputs[n] = TimedInput::default();
+                for &member in &self.group_members[n] {
+                    released[self.layout_table[member]] = true;
+                }
+            }
         }
 
-        if let FeedbackMode::Once = self.feedback_mode {
-            // Reset inputs every tick in "Once" mode
-            // otherwise the effect will keep playing until intensity becomes zero
-            self.dot_active_states
-                .lock()
-                .await
-                .iter_mut()
-                .for_each(|s| *s = false);
-            self.dot_intensities
-                .lock()
-                .await
-                .iter_mut()
-                .for_each(|p| *p = 0.0);
+        for (i, &released) in released.iter().enumerate() {
+            // Once pulses and minimum on-time holds
+            if dot_state.pulse_deadlines[i].is_some_and(|d| d <= now) {
+                dot_state.pulse_deadlines[i] = None;
+                dot_state.intensities[i] = 0.0;
+                dot_state.active_states[i] = false;
+            }
+
+            if released {
+                let input = self.combined_input(&dot_state, &group_inputs, i);
+                self.update_dot(&mut dot_state, i, input, None, feedback_mode, now);
+            }
         }
+    }
 
-        // only send if there's something to send
-        if !effect.tracks.is_empty() {
-            Some(effect)
-        } else {
-            None
+    pub async fn build_effects(&mut self) -> Vec<true_gear_message::Effect> {
+        self.release_inputs().await;
+
+        if *self.playback_state.lock().await != PlaybackState::Playing {
+            return Vec::new();
+        }
+
+        let RuntimeSettings {
+            shake_intensity,
+            electrical_intensity,
+            electrical_interval,
+            ..
+        } = *self.settings.lock().await;
+
+        let now = Instant::now();
+        let dt = {
+            let mut last_tick = self.last_tick.lock().await;
+            let dt = last_tick.map_or(Duration::ZERO, |t| now - t);
+            *last_tick = Some(now);
+            dt
+        };
+        let track_duration = Duration::from_millis(TRACK_DURATION_MS as u64);
+
+        let mut contact_frame = Frame::new(electrical_interval);
+
+        let mut dot_state = self.dot_state.lock().await;
+        for i in 0..NUM_DOTS {
+            let (channel, base_intensity) = if i < NUM_SHAKES {
+                (&self.config.shake, shake_intensity)
+            } else {
+                (
+                    &self.config.electrical,
+                    electrical_intensity[i - NUM_SHAKES],
+                )
+            };
+
+            // releases are left to the envelope
+            let target = if dot_state.active_states[i] {
+                dot_state.levels[i]
+            } else {
+                0.0
+            };
+
+            // the impact boost fades out on top of the input
+            let (boost_start, boost_end) = match (dot_state.impacts[i], &channel.impact_boost) {
+                (Some((boost, time)), Some(impact_boost)) => (
+                    impact_boost.level(boost, now - time),
+                    impact_boost.level(boost, now + track_duration - time),
+                ),
+                _ => (0.0, 0.0),
+            };
+            if boost_start <= 0.0 {
+                dot_state.impacts[i] = None;
+            }
+
+            // ramp the envelope towards the input, and project it to the end of the track
+            let start =
+                Self::envelope_step(channel, dot_state.envelopes[i], target + boost_start, dt);
+            let end = Self::envelope_step(channel, start, target + boost_end, track_duration);
+            dot_state.envelopes[i] = start;
+
+            contact_frame.start[i] = Self::scale_intensity(channel, base_intensity, start);
+            contact_frame.end[i] = Self::scale_intensity(channel, base_intensity, end);
+        }
+        drop(dot_state);
+
+        let mut mixer = Mixer::new(self.calibration.gains);
+
+        let contact_layer = &self.config.mixer.contact;
+        mixer.add_layer(Layer {
+            name: CONTACT_LAYER_NAME.to_string(),
+            priority: contact_layer.priority.unwrap_or(0),
+            blend: contact_layer.blend,
+            frame: contact_frame,
+        });
+
+        let mut playing_effects = self.playing_effects.lock().await;
+        playing_effects.retain(|p| !p.is_finished(now));
+        let effects_layer = &self.config.mixer.effects;
+        for playing_effect in playing_effects.iter() {
+            mixer.add_layer(Layer {
+                name: format!("{}{}", EFFECT_LAYER_PREFIX, playing_effect.name),
+                priority: effects_layer.priority.unwrap_or(playing_effect.priority()),
+                blend: effects_layer.blend,
+                frame: playing_effect.render(now),
+            });
+        }
+        drop(playing_effects);
+
+        let generators_layer = &self.config.mixer.generators;
+        for generator in self.generators.lock().await.iter() {
+            let Some((start, end)) = generator.render(now) else {
+                continue;
+            };
+            let mut frame = Frame::new(electrical_interval);
+            for i in 0..NUM_SHAKES {
+                frame.start[i] =
+                    Self::scale_intensity(&self.config.shake, shake_intensity, start[i]);
+                frame.end[i] = Self::scale_intensity(&self.config.shake, shake_intensity, end[i]);
+            }
+            mixer.add_layer(Layer {
+                name: format!("{}{}", GENERATOR_LAYER_PREFIX, generator.kind.name()),
+                priority: generators_layer.priority.unwrap_or(0),
+                blend: generators_layer.blend,
+                frame,
+            });
+        }
+
+        // dots played at a single level, through their channel like contact input
+        let level_frame = |dots: &[usize], start: f32, end: f32| {
+            let mut frame = Frame::new(electrical_interval);
+            for &i in dots {
+                let (channel, base_intensity) = if i < NUM_SHAKES {
+                    (&self.config.shake, shake_intensity)
+                } else {
+                    (
+                        &self.config.electrical,
+                        electrical_intensity[i - NUM_SHAKES],
+                    )
+                };
+                frame.start[i] = Self::scale_intensity(channel, base_intensity, start);
+                frame.end[i] = Self::scale_intensity(channel, base_intensity, end);
+            }
+            frame
+        };
+
+        if let Some(heart_rate) = &self.config.heart_rate
+            && let Some((start, end)) = self.heart_rate.lock().await.render(heart_rate, now)
+        {
+            mixer.add_layer(Layer {
+                name: HEART_RATE_LAYER_NAME.to_string(),
+                priority: generators_layer.priority.unwrap_or(0),
+                blend: generators_layer.blend,
+                frame: level_frame(&self.heart_rate_members, start, end),
+            });
+        }
+
+        let reactions_layer = &self.config.mixer.reactions;
+        let mut vrchat = self.vrchat.lock().await;
+        if let Some(landing) = &self.config.vrchat.landing
+            && let Some((start, end)) = vrchat.landing_levels(landing, now, track_duration)
+        {
+            mixer.add_layer(Layer {
+                name: LANDING_LAYER_NAME.to_string(),
+                priority: reactions_layer.priority.unwrap_or(0),
+                blend: reactions_layer.blend,
+                frame: level_frame(&self.landing_members, start, end),
+            });
+        }
+        if let Some(wind) = &self.config.vrchat.wind
+            && let Some(level) = vrchat.wind_level(wind, now)
+        {
+            mixer.add_layer(Layer {
+                name: WIND_LAYER_NAME.to_string(),
+                priority: reactions_layer.priority.unwrap_or(0),
+                blend: reactions_layer.blend,
+                frame: level_frame(&self.wind_members, level, level),
+            });
+        }
+        drop(vrchat);
+
+        mixer.build_effects()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{ArmPanConfig, OnceConfig},
+        dot_mapping::DotGroup,
+    };
+
+    const B1: usize = 5;
+    const B1_ADDRESS: &str = "/avatar/parameters/TrueGearB1";
+    const CHEST_ADDRESS: &str = "/avatar/parameters/Chest";
+
+    fn chest_group() -> DotGroup {
+        DotGroup {
+            name: "Chest".to_string(),
+            dots: vec!["TrueGearB1".to_string()],
+            zones: Vec::new(),
         }
     }
+
+    fn mapper(feedback_mode: FeedbackMode, config: Config) -> ProtocalMapper {
+        let mapper = ProtocalMapper {
+            group_members: Arc::new(config.groups.iter().map(|g| g.members()).collect()),
+            group_inputs: Arc::new(Mutex::new(vec![TimedInput::default(); config.groups.len()])),
+            config: Arc::new(config),
+            ..Default::default()
+        };
+        mapper.settings.try_lock().unwrap().feedback_mode = feedback_mode;
+        mapper
+    }
+
+    async fn send(mapper: &mut ProtocalMapper, addr: &str, args: Vec<OscType>) {
+        let msg = OscMessage {
+            addr: addr.to_string(),
+            args,
+        };
+        mapper.consume_osc_packet(&OscPacket::Message(msg)).await;
+    }
+
+    // intensity and active state of a dot
+    async fn dot(mapper: &ProtocalMapper, i: usize) -> (f32, bool) {
+        let dot_state = mapper.dot_state.lock().await;
+        (dot_state.intensities[i], dot_state.active_states[i])
+    }
+
+    async fn advance(mapper: &mut ProtocalMapper, ms: u64) {
+        tokio::time::advance(Duration::from_millis(ms)).await;
+        mapper.release_inputs().await;
+    }
+
+    #[test]
+    fn int_range_normalizes_without_overflow() {
+        let range = IntRange { min: 1, max: 255 };
+        assert_eq!(range.normalize(1), 0.0);
+        assert_eq!(range.normalize(255), 1.0);
+        assert_eq!(range.normalize(i64::MIN), 0.0);
+        assert_eq!(range.normalize(i64::MAX), 1.0);
+
+        let range = IntRange {
+            min: i64::MIN,
+            max: i64::MAX,
+        };
+        assert!((range.normalize(0) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn durations_are_validated() {
+        let duration = |arg: OscType| ProtocalMapper::extract_duration(&arg);
+        assert_eq!(
+            duration(OscType::Int(300)),
+            Some(Duration::from_millis(300))
+        );
+        assert_eq!(
+            duration(OscType::String(" 50 ".to_string())),
+            Some(Duration::from_millis(50))
+        );
+        assert_eq!(duration(OscType::Int(0)), None);
+        assert_eq!(duration(OscType::Float(-1.0)), None);
+        assert_eq!(duration(OscType::String("inf".to_string())), None);
+        assert_eq!(duration(OscType::Float(f32::NAN)), None);
+        assert_eq!(duration(OscType::Double(1e30)), Some(MAX_PULSE_DURATION));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timed_pulses_end_at_their_deadline() {
+        let mut mapper = mapper(FeedbackMode::Continuous, Config::default());
+        send(
+            &mut mapper,
+            B1_ADDRESS,
+            vec![OscType::Float(0.8), OscType::Int(100)],
+        )
+        .await;
+        assert_eq!(dot(&mapper, B1).await, (0.8, true));
+
+        advance(&mut mapper, 99).await;
+        assert_eq!(dot(&mapper, B1).await, (0.8, true));
+        advance(&mut mapper, 1).await;
+        assert_eq!(dot(&mapper, B1).await, (0.0, false));
+
+        // untimed inputs stay on
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.5)]).await;
+        advance(&mut mapper, 1000).await;
+        assert_eq!(dot(&mapper, B1).await, (0.5, true));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn once_mode_fires_a_pulse_per_rising_edge() {
+        let config = Config {
+            once: OnceConfig {
+                pulse_ms: 150,
+                rearm_threshold: 0.2,
+            },
+            ..Default::default()
+        };
+        let mut mapper = mapper(FeedbackMode::Once, config);
+
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.8)]).await;
+        assert_eq!(dot(&mapper, B1).await, (0.8, true));
+        advance(&mut mapper, 150).await;
+        assert_eq!(dot(&mapper, B1).await, (0.0, false));
+
+        // a held input does not fire again
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.9)]).await;
+        assert_eq!(dot(&mapper, B1).await, (0.0, false));
+
+        // dropping to the threshold re-arms the dot
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.2)]).await;
+        assert_eq!(dot(&mapper, B1).await, (0.0, false));
+        send(
+            &mut mapper,
+            B1_ADDRESS,
+            vec![OscType::Float(0.7), OscType::Int(300)],
+        )
+        .await;
+        assert_eq!(dot(&mapper, B1).await, (0.7, true));
+        advance(&mut mapper, 150).await;
+        assert_eq!(dot(&mapper, B1).await, (0.7, true));
+        advance(&mut mapper, 150).await;
+        assert_eq!(dot(&mapper, B1).await, (0.0, false));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn silent_inputs_are_switched_off() {
+        let config = Config {
+            stale_timeout_ms: 500,
+            ..Default::default()
+        };
+        let mut mapper = mapper(FeedbackMode::Continuous, config);
+
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.6)]).await;
+        advance(&mut mapper, 400).await;
+        // a new input restarts the timeout
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.6)]).await;
+        advance(&mut mapper, 400).await;
+        assert_eq!(dot(&mapper, B1).await, (0.6, true));
+        advance(&mut mapper, 100).await;
+        assert_eq!(dot(&mapper, B1).await, (0.0, false));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn activation_has_hysteresis() {
+        let mut config = Config::default();
+        config.shake.activation_threshold = 0.5;
+        config.shake.deactivation_threshold = Some(0.2);
+        let mut mapper = mapper(FeedbackMode::Continuous, config);
+
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.4)]).await;
+        assert!(!dot(&mapper, B1).await.1);
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.6)]).await;
+        assert_eq!(dot(&mapper, B1).await, (0.6, true));
+        // stays on between the two thresholds
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.3)]).await;
+        assert_eq!(dot(&mapper, B1).await, (0.3, true));
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.2)]).await;
+        assert!(!dot(&mapper, B1).await.1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dots_are_held_for_their_minimum_on_time() {
+        let mut config = Config::default();
+        config.shake.min_on_ms = 300;
+        let mut mapper = mapper(FeedbackMode::Continuous, config);
+
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.8)]).await;
+        advance(&mut mapper, 100).await;
+        // held at its last intensity
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.0)]).await;
+        assert_eq!(dot(&mapper, B1).await, (0.8, true));
+        advance(&mut mapper, 199).await;
+        assert_eq!(dot(&mapper, B1).await, (0.8, true));
+        advance(&mut mapper, 1).await;
+        assert_eq!(dot(&mapper, B1).await, (0.0, false));
+
+        // inputs released after the minimum on-time switch off at once
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.8)]).await;
+        advance(&mut mapper, 300).await;
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.0)]).await;
+        assert_eq!(dot(&mapper, B1).await, (0.0, false));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn arm_pan_keeps_the_pulse_deadline() {
+        let config = Config {
+            arm_pan: Some(ArmPanConfig {
+                address: "/a".to_string(),
+                pan_address: "/p".to_string(),
+            }),
+            ..Default::default()
+        };
+        let mut mapper = mapper(FeedbackMode::Continuous, config);
+        let left = Dot::LEFT_ARM.compact_index();
+        let right = Dot::RIGHT_ARM.compact_index();
+
+        send(
+            &mut mapper,
+            "/a",
+            vec![OscType::Float(1.0), OscType::Int(100)],
+        )
+        .await;
+        assert_eq!(dot(&mapper, left).await, (1.0, true));
+        advance(&mut mapper, 50).await;
+        send(&mut mapper, "/p", vec![OscType::Float(0.75)]).await;
+        assert_eq!(dot(&mapper, left).await, (0.5, true));
+        assert_eq!(dot(&mapper, right).await, (1.0, true));
+
+        // the pulse still ends at its original deadline
+        advance(&mut mapper, 50).await;
+        assert_eq!(dot(&mapper, left).await, (0.0, false));
+        assert_eq!(dot(&mapper, right).await, (0.0, false));
+
+        // and is not replayed by a later pan
+        send(&mut mapper, "/p", vec![OscType::Float(0.5)]).await;
+        assert_eq!(dot(&mapper, left).await, (0.0, false));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn groups_combine_with_dot_inputs_by_max() {
+        let config = Config {
+            groups: vec![chest_group()],
+            ..Default::default()
+        };
+        let mut mapper = mapper(FeedbackMode::Continuous, config);
+
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.3)]).await;
+        send(&mut mapper, CHEST_ADDRESS, vec![OscType::Float(0.6)]).await;
+        assert_eq!(dot(&mapper, B1).await, (0.6, true));
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.8)]).await;
+        assert_eq!(dot(&mapper, B1).await, (0.8, true));
+
+        // a timed group pulse leaves the dot to its own input when it ends
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.3)]).await;
+        send(
+            &mut mapper,
+            CHEST_ADDRESS,
+            vec![OscType::Float(0.9), OscType::Int(100)],
+        )
+        .await;
+        assert_eq!(dot(&mapper, B1).await, (0.9, true));
+        advance(&mut mapper, 100).await;
+        assert_eq!(dot(&mapper, B1).await, (0.3, true));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn groups_go_through_the_dot_activation() {
+        let mut config = Config {
+            groups: vec![chest_group()],
+            ..Default::default()
+        };
+        config.shake.activation_threshold = 0.5;
+        config.shake.min_on_ms = 300;
+        let mut mapper = mapper(FeedbackMode::Continuous, config);
+
+        send(&mut mapper, CHEST_ADDRESS, vec![OscType::Float(0.4)]).await;
+        assert!(!dot(&mapper, B1).await.1);
+        send(&mut mapper, CHEST_ADDRESS, vec![OscType::Float(0.6)]).await;
+        assert_eq!(dot(&mapper, B1).await, (0.6, true));
+        send(&mut mapper, CHEST_ADDRESS, vec![OscType::Float(0.0)]).await;
+        assert_eq!(dot(&mapper, B1).await, (0.6, true));
+        advance(&mut mapper, 300).await;
+        assert_eq!(dot(&mapper, B1).await, (0.0, false));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn groups_fire_once_per_rising_edge() {
+        let config = Config {
+            groups: vec![chest_group()],
+            ..Default::default()
+        };
+        let mut mapper = mapper(FeedbackMode::Once, config);
+
+        send(&mut mapper, CHEST_ADDRESS, vec![OscType::Float(0.8)]).await;
+        assert_eq!(dot(&mapper, B1).await, (0.8, true));
+        advance(&mut mapper, 150).await;
+        assert_eq!(dot(&mapper, B1).await, (0.0, false));
+
+        // held by the group, the dot's own input is no new edge
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.9)]).await;
+        assert_eq!(dot(&mapper, B1).await, (0.0, false));
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.0)]).await;
+        send(&mut mapper, CHEST_ADDRESS, vec![OscType::Float(0.0)]).await;
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.9)]).await;
+        assert_eq!(dot(&mapper, B1).await, (0.9, true));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn inputs_are_smoothed_per_sample() {
+        let mut config = Config::default();
+        config.shake.smoothing = Smoothing::Ema {
+            time_constant_ms: 100.0,
+        };
+        let mut mapper = mapper(FeedbackMode::Continuous, config);
+        let level = |mapper: &ProtocalMapper| mapper.dot_state.try_lock().unwrap().levels[B1];
+
+        // the first sample after switching on is played as is
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(0.2)]).await;
+        assert_eq!(level(&mapper), 0.2);
+        advance(&mut mapper, 100).await;
+        send(&mut mapper, B1_ADDRESS, vec![OscType::Float(1.0)]).await;
+        let expected = 0.2 + 0.8 * (1.0 - (-1.0f32).exp());
+        assert!((level(&mapper) - expected).abs() < 1e-6);
+
+        // output ticks do not move the level between samples
+        mapper.build_effects().await;
+        tokio::time::advance(Duration::from_millis(100)).await;
+        mapper.build_effects().await;
+        assert!((level(&mapper) - expected).abs() < 1e-6);
+    }
 }
@@ -0,0 +1,408 @@
+use crate::{
+    control::MAX_INTENSITY,
+    dot_mapping::Channel,
+    geometry::{DOTS, Dot, NUM_DOTS, NUM_ELECTRICAL, NUM_SHAKES},
+    true_gear_message,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+// dots are grouped into tracks by intensity, rounded to this step
+const INTENSITY_STEP: u16 = 5;
+
+// duration of each track sent to TrueGear
+pub const TRACK_DURATION_MS: u16 = 150;
+
+// resolution used to catch short pulses between two ticks
+pub const SAMPLE_STEP_MS: u32 = 10;
+
+// Per-dot levels at the start and the end of the next track, from the levels sampled at an offset
+// in ms into the track. Pulses shorter than a track would fall between the two ends, they are
+// played at their peak over the whole track.
+pub fn sample_track<const N: usize>(
+    mut sample: impl FnMut(u32) -> [f32; N],
+) -> ([f32; N], [f32; N]) {
+    let mut start = sample(0);
+    let mut end = sample(TRACK_DURATION_MS as u32);
+    for offset in (SAMPLE_STEP_MS..TRACK_DURATION_MS as u32).step_by(SAMPLE_STEP_MS as usize) {
+        for (i, level) in sample(offset).into_iter().enumerate() {
+            if level > start[i].max(end[i]) {
+                start[i] = level;
+                end[i] = level;
+            }
+        }
+    }
+    (start, end)
+}
+
+// How a layer combines with the layers of lower priority
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    // keep the strongest intensity
+    #[default]
+    Max,
+    // add intensities, clamped to the maximum intensity
+    Sum,
+    // replace lower layers wherever this layer is active
+    Override,
+}
+
+// Per-dot intensities of a layer over the next track, in TrueGear intensity units
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub start: [f32; NUM_DOTS],
+    pub end: [f32; NUM_DOTS],
+    // per arm, left then right
+    pub electrical_intervals: [u8; NUM_ELECTRICAL],
+}
+
+impl Frame {
+    pub fn new(electrical_intervals: [u8; NUM_ELECTRICAL]) -> Self {
+        Self {
+            start: [0.0; NUM_DOTS],
+            end: [0.0; NUM_DOTS],
+            electrical_intervals,
+        }
+    }
+}
+
+// A source of feedback, sent to TrueGear as its own effect
+#[derive(Debug, Clone)]
+pub struct Layer {
+    // used as both uuid and name of the layer's effect
+    pub name: String,
+    pub priority: u16,
+    pub blend: BlendMode,
+    pub frame: Frame,
+}
+
+// Combines the layers of one tick into effects
+#[derive(Debug)]
+pub struct Mixer {
+    layers: Vec<Layer>,
+    // per-dot calibration gains applied to the mixed intensities
+    gains: [f32; NUM_DOTS],
+}
+
+impl Mixer {
+    pub fn new(gains: [f32; NUM_DOTS]) -> Self {
+        Self {
+            layers: Vec::new(),
+            gains,
+        }
+    }
+
+    pub fn add_layer(&mut self, layer: Layer) {
+        self.layers.push(layer);
+    }
+
+    fn quantize_intensity(intensity: f32) -> u16 {
+        let intensity = intensity.round().clamp(0.0, MAX_INTENSITY as f32) as u16;
+        // round to the nearest step; keep non-zero intensities audible
+        let level = (intensity + INTENSITY_STEP / 2) / INTENSITY_STEP * INTENSITY_STEP;
+        if intensity > 0 && level == 0 {
+            INTENSITY_STEP
+        } else {
+            level
+        }
+    }
+
+    // A track playing on the given dots, dots on another channel than the track's are left out
+    pub fn build_track(
+        channel: Channel,
+        start_intensity: u16,
+        end_intensity: u16,
+        interval: u8,
+        dots: &[Dot],
+    ) -> true_gear_message::Track {
+        if dots.iter().any(|dot| dot.channel() != channel) {
+            tracing::error!(
+                "Dots of another channel in a {:?} track: {:?}",
+                channel,
+                dots
+            );
+        }
+
+        let intensity_mode = if start_intensity == end_intensity {
+            true_gear_message::IntensityMode::Const
+        } else {
+            true_gear_message::IntensityMode::Fade
+        };
+
+        true_gear_message::Track {
+            action_type: channel.into(),
+            intensity_mode,
+            stop_name: "".to_string(),
+            start_intensity,
+            end_intensity,
+            start_time: 0,
+            end_time: TRACK_DURATION_MS,
+            interval,
+            once: false,
+            index: dots
+                .iter()
+                .filter(|dot| dot.channel() == channel)
+                .map(Dot::wire_id)
+                .collect(),
+        }
+    }
+
+    // Blend the layers from the lowest to the highest priority. Each dot is then played by the
+    // layer that contributed most to it, so that every layer keeps its own uuid and priority.
+    pub fn build_effects(mut self) -> Vec<true_gear_message::Effect> {
+        // stable sort, layers of equal priority keep their order
+        self.layers.sort_by_key(|layer| layer.priority);
+
+        let mut start = [0 as f32; NUM_DOTS];
+        let mut end = [0 as f32; NUM_DOTS];
+        let mut owners: [Option<usize>; NUM_DOTS] = [None; NUM_DOTS];
+
+        for (l, layer) in self.layers.iter().enumerate() {
+            for i in 0..NUM_DOTS {
+                let (s, e) = (layer.frame.start[i], layer.frame.end[i]);
+                if s <= 0.0 && e <= 0.0 {
+                    continue;
+                }
+
+                match layer.blend {
+                    BlendMode::Max => {
+                        if s.max(e) >= start[i].max(end[i]) {
+                            owners[i] = Some(l);
+                        }
+                        start[i] = start[i].max(s);
+                        end[i] = end[i].max(e);
+                    }
+                    BlendMode::Sum => {
+                        start[i] = (start[i] + s).min(MAX_INTENSITY as f32);
+                        end[i] = (end[i] + e).min(MAX_INTENSITY as f32);
+                        owners[i] = Some(l);
+                    }
+                    BlendMode::Override => {
+                        start[i] = s;
+                        end[i] = e;
+                        owners[i] = Some(l);
+                    }
+                }
+            }
+        }
+
+        for i in 0..NUM_DOTS {
+            start[i] = (start[i] * self.gains[i]).min(MAX_INTENSITY as f32);
+            end[i] = (end[i] * self.gains[i]).min(MAX_INTENSITY as f32);
+        }
+
+        let mut effects = Vec::new();
+
+        for (l, layer) in self.layers.iter().enumerate() {
+            // group shake dots by quantized start / end intensity, so that each dot plays at its
+            // own strength while keeping the number of tracks small
+            let mut shake_levels: BTreeMap<(u16, u16), Vec<Dot>> = BTreeMap::new();
+            // each arm gets its own track, with its own interval
+            let mut electrical_tracks = Vec::new();
+
+            for i in (0..NUM_DOTS).filter(|&i| owners[i] == Some(l)) {
+                let levels = (
+                    Self::quantize_intensity(start[i]),
+                    Self::quantize_intensity(end[i]),
+                );
+                if levels == (0, 0) {
+                    continue;
+                }
+                let dot = DOTS[i];
+                match dot.channel() {
+                    Channel::Shake => shake_levels.entry(levels).or_default().push(dot),
+                    Channel::Electrical => electrical_tracks.push(Self::build_track(
+                        Channel::Electrical,
+                        levels.0,
+                        levels.1,
+                        layer.frame.electrical_intervals[i - NUM_SHAKES],
+                        &[dot],
+                    )),
+                }
+            }
+
+            let shake_tracks = shake_levels.into_iter().map(|((start, end), dots)| {
+                Self::build_track(Channel::Shake, start, end, 0, &dots)
+            });
+
+            let tracks: Vec<_> = shake_tracks.chain(electrical_tracks).collect();
+
+            // only send layers with something to play
+            if tracks.is_empty() {
+                continue;
+            }
+
+            effects.push(true_gear_message::Effect {
+                uuid: layer.name.clone(),
+                name: layer.name.clone(),
+                keep: false,
+                priority: layer.priority,
+                tracks,
+            });
+        }
+
+        effects
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // B1 and A1, wire IDs 0 and 1
+    const B1: usize = 5;
+    const A1: usize = 0;
+
+    fn layer(name: &str, priority: u16, blend: BlendMode, dots: &[(usize, f32)]) -> Layer {
+        let mut frame = Frame::new([0; NUM_ELECTRICAL]);
+        for &(i, intensity) in dots {
+            frame.start[i] = intensity;
+            frame.end[i] = intensity;
+        }
+        Layer {
+            name: name.to_string(),
+            priority,
+            blend,
+            frame,
+        }
+    }
+
+    // (layer name, start intensity, wire IDs) of each track
+    fn tracks(effects: &[true_gear_message::Effect]) -> Vec<(String, u16, Vec<u8>)> {
+        effects
+            .iter()
+            .flat_map(|effect| {
+                effect.tracks.iter().map(|track| {
+                    (
+                        effect.name.clone(),
+                        track.start_intensity,
+                        track.index.clone(),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    fn mix(layers: Vec<Layer>) -> Vec<(String, u16, Vec<u8>)> {
+        let mut mixer = Mixer::new([1.0; NUM_DOTS]);
+        for layer in layers {
+            mixer.add_layer(layer);
+        }
+        tracks(&mixer.build_effects())
+    }
+
+    #[test]
+    fn quantize_rounds_to_steps() {
+        assert_eq!(Mixer::quantize_intensity(0.0), 0);
+        assert_eq!(Mixer::quantize_intensity(52.0), 50);
+        assert_eq!(Mixer::quantize_intensity(53.0), 55);
+        assert_eq!(Mixer::quantize_intensity(500.0), MAX_INTENSITY);
+        assert_eq!(Mixer::quantize_intensity(-10.0), 0);
+        // non-zero intensities stay audible
+        assert_eq!(Mixer::quantize_intensity(1.0), INTENSITY_STEP);
+    }
+
+    #[test]
+    fn max_blend_keeps_the_strongest_layer() {
+        let tracks = mix(vec![
+            layer("low", 0, BlendMode::Max, &[(B1, 80.0), (A1, 40.0)]),
+            layer("high", 1, BlendMode::Max, &[(B1, 50.0)]),
+        ]);
+        // each dot is played by the layer contributing most to it
+        assert_eq!(
+            tracks,
+            [
+                ("low".to_string(), 40, vec![1]),
+                ("low".to_string(), 80, vec![0])
+            ]
+        );
+    }
+
+    #[test]
+    fn sum_blend_adds_and_clamps() {
+        let tracks = mix(vec![
+            layer("low", 0, BlendMode::Max, &[(B1, 60.0), (A1, 100.0)]),
+            layer("high", 1, BlendMode::Sum, &[(B1, 30.0), (A1, 100.0)]),
+        ]);
+        assert_eq!(
+            tracks,
+            [
+                ("high".to_string(), 90, vec![0]),
+                ("high".to_string(), 150, vec![1])
+            ]
+        );
+    }
+
+    #[test]
+    fn override_blend_replaces_lower_layers() {
+        let tracks = mix(vec![
+            layer("low", 0, BlendMode::Max, &[(B1, 100.0), (A1, 100.0)]),
+            layer("high", 1, BlendMode::Override, &[(B1, 20.0)]),
+        ]);
+        // only where the overriding layer is active
+        assert_eq!(
+            tracks,
+            [
+                ("low".to_string(), 100, vec![1]),
+                ("high".to_string(), 20, vec![0])
+            ]
+        );
+    }
+
+    #[test]
+    fn layers_are_blended_by_priority() {
+        // added out of order, the override still applies over the lower priority layer
+        let tracks = mix(vec![
+            layer("high", 1, BlendMode::Override, &[(B1, 20.0)]),
+            layer("low", 0, BlendMode::Max, &[(B1, 100.0)]),
+        ]);
+        assert_eq!(tracks, [("high".to_string(), 20, vec![0])]);
+    }
+
+    #[test]
+    fn sample_track_catches_short_pulses() {
+        // a pulse from 40 ms to 60 ms into the track
+        let (start, end) =
+            sample_track(|offset| [if (40..60).contains(&offset) { 1.0 } else { 0.0 }]);
+        assert_eq!((start, end), ([1.0], [1.0]));
+
+        let (start, end) = sample_track(|offset| [offset as f32]);
+        assert_eq!((start, end), ([0.0], [TRACK_DURATION_MS as f32]));
+    }
+
+    #[test]
+    fn each_arm_plays_its_own_interval() {
+        let mut mixer = Mixer::new([1.0; NUM_DOTS]);
+        let mut arms = layer("arms", 0, BlendMode::Max, &[(40, 60.0), (41, 60.0)]);
+        arms.frame.electrical_intervals = [5, 20];
+        mixer.add_layer(arms);
+
+        let effects = mixer.build_effects();
+        let intervals: Vec<_> = effects[0]
+            .tracks
+            .iter()
+            .map(|track| (track.index.clone(), track.interval))
+            .collect();
+        // wire IDs 0 and 100 are the left and right arm
+        assert_eq!(intervals, [(vec![0], 5), (vec![100], 20)]);
+    }
+
+    #[test]
+    fn calibration_gains_scale_the_mixed_intensities() {
+        let mut gains = [1.0; NUM_DOTS];
+        gains[B1] = 0.5;
+        gains[A1] = 3.0;
+        let mut mixer = Mixer::new(gains);
+        mixer.add_layer(layer("layer", 0, BlendMode::Max, &[(B1, 80.0), (A1, 80.0)]));
+
+        // clamped to the maximum intensity
+        assert_eq!(
+            tracks(&mixer.build_effects()),
+            [
+                ("layer".to_string(), 40, vec![0]),
+                ("layer".to_string(), MAX_INTENSITY, vec![1])
+            ]
+        );
+    }
+}
@@ -5,25 +5,16 @@ use std::{error::Error, time::Duration};
 pub struct Sender {
     true_gear_websocket: crate::websocket::TrueGearWebsocketClient,
     shared_state: ProtocalMapper,
-    shake_intensity: u16,
-    electrical_intensity: u16,
-    electrical_interval: u8,
 }
 
 impl Sender {
     pub fn new(
         true_gear_websocket: crate::websocket::TrueGearWebsocketClient,
         shared_state: ProtocalMapper,
-        shake_intensity: u16,
-        electrical_intensity: u16,
-        electrical_interval: u8,
     ) -> Self {
         Self {
             true_gear_websocket,
             shared_state,
-            shake_intensity,
-            electrical_intensity,
-            electrical_interval,
         }
     }
 
@@ -33,19 +24,20 @@ impl Sender {
             tracing::warn!("WebSocket connection error: {}", e);
         }
         loop {
-            tokio::time::sleep(Duration::from_millis(100)).await;
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_millis(100)) => {}
+                _ = self.shared_state.effects_pending() => {
+                    for effect in self.shared_state.take_pending_effects().await {
+                        if let Err(e) = self.true_gear_websocket.send_play_effect(&effect).await {
+                            tracing::error!("WebSocket connection error: {}", e);
+                        }
+                    }
+                }
+            }
 
-            let maybe_effect = self
-                .shared_state
-                .clone()
-                .build_effect(
-                    self.shake_intensity,
-                    self.electrical_intensity,
-                    self.electrical_interval,
-                )
-                .await;
+            let effects = self.shared_state.clone().build_effects().await;
 
-            if let Some(effect) = maybe_effect {
+            for effect in effects {
                 // ignore send errors (reconnect will happen on next send)
                 if let Err(e) = self.true_gear_websocket.send_play_effect(&effect).await {
                     tracing::error!("WebSocket connection error: {}", e);
@@ -57,18 +49,9 @@ impl Sender {
     pub async fn build(
         truegear_ws_url: String,
         shared_state: ProtocalMapper,
-        shake_intensity: u16,
-        electrical_intensity: u16,
-        electrical_interval: u8,
     ) -> Result<Self, Box<dyn Error>> {
         let true_gear_websocket = TrueGearWebsocketClient::new(truegear_ws_url);
-        Ok(Self::new(
-            true_gear_websocket,
-            shared_state,
-            shake_intensity,
-            electrical_intensity,
-            electrical_interval,
-        ))
+        Ok(Self::new(true_gear_websocket, shared_state))
     }
 
     pub async fn close(&mut self) {
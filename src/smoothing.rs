@@ -0,0 +1,135 @@
+use serde::Deserialize;
+use std::{error::Error, f32::consts::PI, time::Duration};
+
+// Low-pass filter applied to the input level of each dot while it is active
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Smoothing {
+    #[default]
+    None,
+    // exponential moving average, reaching 63% of a step after time_constant_ms
+    Ema {
+        time_constant_ms: f32,
+    },
+    // one-euro filter: smooths slow changes with min_cutoff_hz, and follows fast changes
+    // more closely as beta grows
+    OneEuro {
+        min_cutoff_hz: f32,
+        beta: f32,
+        #[serde(default = "default_derivative_cutoff_hz")]
+        derivative_cutoff_hz: f32,
+    },
+}
+
+fn default_derivative_cutoff_hz() -> f32 {
+    1.0
+}
+
+// Filter state of a single dot
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SmoothingState {
+    value: Option<f32>,
+    derivative: f32,
+}
+
+impl Smoothing {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let valid = match self {
+            Smoothing::None => true,
+            Smoothing::Ema { time_constant_ms } => *time_constant_ms >= 0.0,
+            Smoothing::OneEuro {
+                min_cutoff_hz,
+                beta,
+                derivative_cutoff_hz,
+            } => *min_cutoff_hz > 0.0 && *beta >= 0.0 && *derivative_cutoff_hz > 0.0,
+        };
+        if !valid {
+            return Err(format!("invalid smoothing {:?}", self).into());
+        }
+        Ok(())
+    }
+
+    // smoothing factor of a first-order low-pass filter over dt
+    fn alpha(cutoff_hz: f32, dt: f32) -> f32 {
+        let tau = 1.0 / (2.0 * PI * cutoff_hz);
+        1.0 / (1.0 + tau / dt)
+    }
+
+    // Feed an input sample taken dt after the previous one, returns the filtered value
+    pub fn update(&self, state: &mut SmoothingState, input: f32, dt: Duration) -> f32 {
+        let dt = dt.as_secs_f32();
+        let Some(previous) = state.value.filter(|_| dt > 0.0) else {
+            // nothing to smooth from yet
+            state.value = Some(input);
+            state.derivative = 0.0;
+            return input;
+        };
+
+        let value = match self {
+            Smoothing::None => input,
+            Smoothing::Ema { time_constant_ms } => {
+                if *time_constant_ms <= 0.0 {
+                    input
+                } else {
+                    let alpha = 1.0 - (-dt * 1000.0 / time_constant_ms).exp();
+                    previous + (input - previous) * alpha
+                }
+            }
+            Smoothing::OneEuro {
+                min_cutoff_hz,
+                beta,
+                derivative_cutoff_hz,
+            } => {
+                let derivative = (input - previous) / dt;
+                let alpha = Self::alpha(*derivative_cutoff_hz, dt);
+                state.derivative += (derivative - state.derivative) * alpha;
+
+                let cutoff_hz = min_cutoff_hz + beta * state.derivative.abs();
+                previous + (input - previous) * Self::alpha(cutoff_hz, dt)
+            }
+        };
+
+        state.value = Some(value);
+        value
+    }
+
+    // Forget the filter state, e.g. once the dot is released
+    pub fn reset(state: &mut SmoothingState) {
+        *state = SmoothingState::default();
+    }
+}
+
+// Extra intensity added on sudden rising edges, so fast contacts feel punchier than slow approaches
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImpactBoost {
+    // boost added per unit of input rise
+    pub gain: f32,
+    // minimum rise speed, in input units per second, for a rise to count as an impact
+    #[serde(default)]
+    pub min_rise_per_s: f32,
+    // time for the boost to fade out
+    pub decay_ms: u64,
+}
+
+impl ImpactBoost {
+    // Boost for an input rising by rise over elapsed, if it is fast enough
+    pub fn boost(&self, rise: f32, elapsed: Option<Duration>) -> Option<f32> {
+        if rise <= 0.0 {
+            return None;
+        }
+        // a rise from silence counts as sudden
+        let is_sudden = elapsed
+            .map(|e| e.as_secs_f32())
+            .is_none_or(|e| e <= 0.0 || rise / e >= self.min_rise_per_s);
+        is_sudden.then_some(self.gain * rise)
+    }
+
+    // Remaining boost level, elapsed after an impact of the given level
+    pub fn level(&self, boost: f32, elapsed: Duration) -> f32 {
+        if self.decay_ms == 0 {
+            return 0.0;
+        }
+        let progress = elapsed.as_secs_f32() * 1000.0 / self.decay_ms as f32;
+        boost * (1.0 - progress).max(0.0)
+    }
+}
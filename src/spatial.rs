@@ -0,0 +1,157 @@
+use crate::geometry::{NUM_COLUMNS, NUM_ROWS, Zone};
+use rosc::OscType;
+use serde::Deserialize;
+use std::error::Error;
+
+// Drives the dots around a position: side, x, y, intensity and an optional pulse duration in ms
+pub const POINT_ADDRESS: &str = "/truegear/point";
+
+// Drives the ring of front and back dots facing a direction: angle in degrees, intensity and an
+// optional height
+pub const DIRECTION_ADDRESS: &str = "/truegear/direction";
+
+// Spread of a point input over the dots around it
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Falloff {
+    // the (up to four) dots around the point, weighted by their distance along each axis
+    #[default]
+    Bilinear,
+    // every dot within radius of the point, fading out linearly with the distance
+    Radial {
+        radius: f32,
+    },
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PointConfig {
+    pub falloff: Falloff,
+}
+
+fn default_spread_deg() -> f32 {
+    60.0
+}
+
+fn default_height_spread() -> f32 {
+    0.75
+}
+
+// Spread of a direction input over the ring of shake dots
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DirectionConfig {
+    // dots fade out linearly up to this angle away from the direction
+    #[serde(default = "default_spread_deg")]
+    pub spread_deg: f32,
+    // with a height, rows fade out linearly up to this distance from it, in Dot::position units
+    #[serde(default = "default_height_spread")]
+    pub height_spread: f32,
+}
+
+impl Default for DirectionConfig {
+    fn default() -> Self {
+        Self {
+            spread_deg: default_spread_deg(),
+            height_spread: default_height_spread(),
+        }
+    }
+}
+
+impl DirectionConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.spread_deg <= 0.0 || self.height_spread <= 0.0 {
+            return Err(format!(
+                "spread_deg and height_spread must be positive, got {} and {}",
+                self.spread_deg, self.height_spread
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    // Weights of the front and back dots facing a direction, as (compact index, weight).
+    // Without a height, every row of the arc plays at full weight.
+    pub fn spread(&self, angle_deg: f32, height: Option<f32>) -> Vec<(usize, f32)> {
+        [Zone::Front, Zone::Back]
+            .into_iter()
+            .flat_map(|zone| zone.dots())
+            .map(|dot| {
+                // shortest angle between the dot and the direction, 0 - 180
+                let difference = (dot.azimuth() - angle_deg).rem_euclid(360.0);
+                let difference = difference.min(360.0 - difference);
+                let angle_weight = (1.0 - difference / self.spread_deg).max(0.0);
+
+                let height_weight = height.map_or(1.0, |height| {
+                    let (_, y) = dot.position();
+                    (1.0 - (y - height.clamp(-1.0, 1.0)).abs() / self.height_spread).max(0.0)
+                });
+                (dot.compact_index(), angle_weight * height_weight)
+            })
+            .filter(|&(_, weight)| weight > 0.0)
+            .collect()
+    }
+}
+
+impl Falloff {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if let Falloff::Radial { radius } = self
+            && *radius <= 0.0
+        {
+            return Err(format!("radius must be positive, got {}", radius).into());
+        }
+        Ok(())
+    }
+
+    // Weights of the dots of a side around a position, as (compact index, weight).
+    // The position uses the coordinates of Dot::position, and is clamped to the grid.
+    pub fn spread(&self, zone: Zone, x: f32, y: f32) -> Vec<(usize, f32)> {
+        let (x, y) = (x.clamp(-1.0, 1.0), y.clamp(-1.0, 1.0));
+        zone.dots()
+            .map(|dot| {
+                let weight = match self {
+                    Falloff::Bilinear => {
+                        // position in grid units
+                        let column = (x + 1.0) / 2.0 * (NUM_COLUMNS - 1) as f32;
+                        let row = (1.0 - y) / 2.0 * (NUM_ROWS - 1) as f32;
+                        let column_weight = 1.0 - (dot.column() as f32 - column).abs();
+                        let row_weight = 1.0 - (dot.row() as f32 - row).abs();
+                        column_weight.max(0.0) * row_weight.max(0.0)
+                    }
+                    Falloff::Radial { radius } => {
+                        let (dot_x, dot_y) = dot.position();
+                        let distance = (dot_x - x).hypot(dot_y - y);
+                        (1.0 - distance / radius).max(0.0)
+                    }
+                };
+                (dot.compact_index(), weight)
+            })
+            .filter(|&(_, weight)| weight > 0.0)
+            .collect()
+    }
+}
+
+// Side of the vest, as "front" / "back", or 0 / 1
+pub fn extract_side(arg: &OscType) -> Option<Zone> {
+    match arg {
+        OscType::String(s) => match s.trim().to_ascii_lowercase().as_str() {
+            "front" => Some(Zone::Front),
+            "back" => Some(Zone::Back),
+            _ => None,
+        },
+        OscType::Int(0) => Some(Zone::Front),
+        OscType::Int(1) => Some(Zone::Back),
+        _ => None,
+    }
+}
+
+// Coordinates are taken as-is, unlike intensities Ints are not normalized
+pub fn extract_coordinate(arg: &OscType) -> Option<f32> {
+    match arg {
+        OscType::Float(f) => Some(*f),
+        OscType::Double(f) => Some(*f as f32),
+        OscType::Int(i) => Some(*i as f32),
+        OscType::String(s) => s.trim().parse::<f32>().ok(),
+        _ => None,
+    }
+}
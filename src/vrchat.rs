@@ -0,0 +1,355 @@
+use crate::geometry::{NUM_ROWS, Zone, parse_dot_names};
+use rosc::{OscMessage, OscType};
+use serde::Deserialize;
+use std::{error::Error, time::Duration};
+use tokio::time::Instant;
+
+// VRChat's built-in avatar parameters
+const VELOCITY_ADDRESSES: [&str; 3] = [
+    "/avatar/parameters/VelocityX",
+    "/avatar/parameters/VelocityY",
+    "/avatar/parameters/VelocityZ",
+];
+const GROUNDED_ADDRESS: &str = "/avatar/parameters/Grounded";
+
+fn dot_names(zone: Zone, rows: std::ops::Range<u8>) -> Vec<String> {
+    zone.dots()
+        .filter(|dot| rows.contains(&dot.row()))
+        .map(|dot| dot.name().to_string())
+        .collect()
+}
+
+fn validate_range(min: f32, max: f32) -> Result<(), Box<dyn Error>> {
+    if min < 0.0 || min >= max {
+        return Err(format!("invalid speed range {} - {}", min, max).into());
+    }
+    Ok(())
+}
+
+// 0.0 at min, 1.0 at max and above
+fn ramp(value: f32, min: f32, max: f32) -> f32 {
+    ((value - min) / (max - min)).clamp(0.0, 1.0)
+}
+
+fn default_landing_dots() -> Vec<String> {
+    [Zone::Front, Zone::Back]
+        .into_iter()
+        .flat_map(|zone| dot_names(zone, NUM_ROWS - 1..NUM_ROWS))
+        .collect()
+}
+
+fn default_min_fall_speed() -> f32 {
+    2.0
+}
+
+fn default_max_fall_speed() -> f32 {
+    10.0
+}
+
+fn default_landing_decay_ms() -> u64 {
+    300
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+// A thump when the avatar lands, scaled by its fall speed
+#[derive(Debug, Clone, Deserialize)]
+pub struct LandingConfig {
+    // dot names, defaults to the bottom row of both sides
+    #[serde(default = "default_landing_dots")]
+    pub dots: Vec<String>,
+    // falls slower than this, in m/s, do not thump
+    #[serde(default = "default_min_fall_speed")]
+    pub min_fall_speed: f32,
+    // falls at this speed and above thump at full intensity
+    #[serde(default = "default_max_fall_speed")]
+    pub max_fall_speed: f32,
+    #[serde(default = "default_intensity")]
+    pub intensity: f32,
+    // time for the thump to fade out
+    #[serde(default = "default_landing_decay_ms")]
+    pub decay_ms: u64,
+}
+
+impl LandingConfig {
+    // Compact indices of the dots the thump plays on, names are checked when loading
+    pub fn members(&self) -> Vec<usize> {
+        parse_dot_names(&self.dots).unwrap_or_default()
+    }
+}
+
+fn default_wind_dots() -> Vec<String> {
+    dot_names(Zone::Front, 0..NUM_ROWS)
+}
+
+fn default_min_wind_speed() -> f32 {
+    8.0
+}
+
+fn default_max_wind_speed() -> f32 {
+    30.0
+}
+
+fn default_wind_intensity() -> f32 {
+    0.4
+}
+
+fn default_wind_timeout_ms() -> u64 {
+    1000
+}
+
+// A buzz growing with the speed of the avatar
+#[derive(Debug, Clone, Deserialize)]
+pub struct WindConfig {
+    // dot names, defaults to the front
+    #[serde(default = "default_wind_dots")]
+    pub dots: Vec<String>,
+    // speeds below this, in m/s, are still
+    #[serde(default = "default_min_wind_speed")]
+    pub min_speed: f32,
+    // speeds at this and above buzz at full intensity
+    #[serde(default = "default_max_wind_speed")]
+    pub max_speed: f32,
+    #[serde(default = "default_wind_intensity")]
+    pub intensity: f32,
+    // stop once the velocity has not been received for this long (0 to disable)
+    #[serde(default = "default_wind_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl WindConfig {
+    // Compact indices of the dots the buzz plays on, names are checked when loading
+    pub fn members(&self) -> Vec<usize> {
+        parse_dot_names(&self.dots).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+impl Hand {
+    // index in the [left, right] gestures
+    fn index(&self) -> usize {
+        match self {
+            Hand::Left => 0,
+            Hand::Right => 1,
+        }
+    }
+}
+
+// Plays a library effect when a hand makes a gesture
+#[derive(Debug, Clone, Deserialize)]
+pub struct GestureEffect {
+    pub hand: Hand,
+    // VRChat gesture value, e.g. 1 for a fist
+    pub gesture: i32,
+    pub effect: String,
+}
+
+// Built-in parameters pausing the haptics while set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PauseParameter {
+    Afk,
+    MuteSelf,
+    InStation,
+}
+
+impl PauseParameter {
+    fn address(&self) -> &'static str {
+        match self {
+            PauseParameter::Afk => "/avatar/parameters/AFK",
+            PauseParameter::MuteSelf => "/avatar/parameters/MuteSelf",
+            PauseParameter::InStation => "/avatar/parameters/InStation",
+        }
+    }
+}
+
+// Reactions to VRChat's built-in avatar parameters, each disabled unless configured
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct VrchatConfig {
+    pub landing: Option<LandingConfig>,
+    pub wind: Option<WindConfig>,
+    pub gesture_effects: Vec<GestureEffect>,
+    pub auto_pause: Vec<PauseParameter>,
+}
+
+impl VrchatConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(landing) = &self.landing {
+            parse_dot_names(&landing.dots)?;
+            validate_range(landing.min_fall_speed, landing.max_fall_speed)?;
+        }
+        if let Some(wind) = &self.wind {
+            parse_dot_names(&wind.dots)?;
+            validate_range(wind.min_speed, wind.max_speed)?;
+        }
+        Ok(())
+    }
+
+    // Effects to play for the gestures that changed between two [left, right] gestures
+    pub fn gesture_effects(&self, previous: &[i32; 2], gestures: &[i32; 2]) -> Vec<String> {
+        self.gesture_effects
+            .iter()
+            .filter(|g| {
+                let hand = g.hand.index();
+                gestures[hand] == g.gesture && previous[hand] != g.gesture
+            })
+            .map(|g| g.effect.clone())
+            .collect()
+    }
+}
+
+// What the mapper should do after a parameter update
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reaction {
+    // a thump started, send it without waiting for the next tick
+    Landed,
+    Pause,
+    Resume,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VrchatState {
+    velocity: [f32; 3],
+    velocity_update: Option<Instant>,
+    grounded: Option<bool>,
+    // fastest downward speed since leaving the ground
+    fall_speed: f32,
+    // level and time of the last thump
+    landing: Option<(f32, Instant)>,
+    // per configured pause parameter
+    pause_flags: Vec<bool>,
+    // whether the haptics were paused by a pause parameter, and should resume once all are clear
+    auto_paused: bool,
+}
+
+impl VrchatState {
+    fn extract_bool(arg: &OscType) -> Option<bool> {
+        match arg {
+            OscType::Bool(b) => Some(*b),
+            OscType::Int(i) => Some(*i != 0),
+            OscType::Float(f) => Some(*f > 0.0),
+            _ => None,
+        }
+    }
+
+    fn extract_float(arg: &OscType) -> Option<f32> {
+        match arg {
+            OscType::Float(f) => Some(*f),
+            OscType::Double(f) => Some(*f as f32),
+            OscType::Int(i) => Some(*i as f32),
+            _ => None,
+        }
+    }
+
+    // Follow a built-in parameter, returns the reactions it causes
+    pub fn update(
+        &mut self,
+        config: &VrchatConfig,
+        msg: &OscMessage,
+        is_playing: bool,
+        now: Instant,
+    ) -> Vec<Reaction> {
+        let mut reactions = Vec::new();
+        let Some(arg) = msg.args.first() else {
+            return reactions;
+        };
+        let addr = msg.addr.as_str();
+
+        if let Some(axis) = VELOCITY_ADDRESSES.iter().position(|&a| a == addr) {
+            let Some(velocity) = Self::extract_float(arg) else {
+                return reactions;
+            };
+            self.velocity[axis] = velocity;
+            self.velocity_update = Some(now);
+            if axis == 1 && self.grounded != Some(true) {
+                self.fall_speed = self.fall_speed.max(-velocity);
+            }
+        } else if addr == GROUNDED_ADDRESS {
+            let Some(grounded) = Self::extract_bool(arg) else {
+                return reactions;
+            };
+            let was_grounded = self.grounded.replace(grounded);
+            if grounded && was_grounded == Some(false) {
+                // VelocityY may already be back to 0 when Grounded arrives, use the fall speed
+                let fall_speed = self.fall_speed.max(-self.velocity[1]);
+                if let Some(landing) = &config.landing
+                    && fall_speed > landing.min_fall_speed
+                {
+                    let level = ramp(fall_speed, landing.min_fall_speed, landing.max_fall_speed);
+                    self.landing = Some((level * landing.intensity, now));
+                    reactions.push(Reaction::Landed);
+                    tracing::debug!("Landed at {} m/s", fall_speed);
+                }
+            }
+            self.fall_speed = 0.0;
+        } else if let Some(n) = config.auto_pause.iter().position(|p| p.address() == addr) {
+            let Some(is_set) = Self::extract_bool(arg) else {
+                return reactions;
+            };
+            self.pause_flags.resize(config.auto_pause.len(), false);
+            let was_set = std::mem::replace(&mut self.pause_flags[n], is_set);
+
+            // only pause when a parameter gets set, so that a manual resume sticks, and only
+            // resume haptics that were paused here, not paused by hand
+            let should_pause = self.pause_flags.iter().any(|&flag| flag);
+            if is_set && !was_set && !self.auto_paused && is_playing {
+                self.auto_paused = true;
+                reactions.push(Reaction::Pause);
+                tracing::info!("{} set, pausing haptics", addr);
+            } else if !should_pause && self.auto_paused {
+                self.auto_paused = false;
+                reactions.push(Reaction::Resume);
+                tracing::info!("{} cleared, resuming haptics", addr);
+            }
+        }
+
+        reactions
+    }
+
+    // A manual pause or resume takes over, the haptics are left as they are once the pause
+    // parameters clear
+    pub fn clear_auto_pause(&mut self) {
+        self.auto_paused = false;
+    }
+
+    // Thump level at the start and the end of the next track, None once faded out
+    pub fn landing_levels(
+        &mut self,
+        config: &LandingConfig,
+        now: Instant,
+        track_duration: Duration,
+    ) -> Option<(f32, f32)> {
+        let (level, time) = self.landing?;
+        let fade = |t: Instant| {
+            let progress = (t - time).as_secs_f32() * 1000.0 / config.decay_ms.max(1) as f32;
+            level * (1.0 - progress).max(0.0)
+        };
+        let start = fade(now);
+        if start <= 0.0 {
+            self.landing = None;
+            return None;
+        }
+        Some((start, fade(now + track_duration)))
+    }
+
+    // Buzz level for the current speed, None while too slow or once the velocity is stale
+    pub fn wind_level(&self, config: &WindConfig, now: Instant) -> Option<f32> {
+        let timeout = Duration::from_millis(config.timeout_ms);
+        if config.timeout_ms > 0 && now - self.velocity_update? >= timeout {
+            return None;
+        }
+        let [x, y, z] = self.velocity;
+        let speed = (x * x + y * y + z * z).sqrt();
+        let level = ramp(speed, config.min_speed, config.max_speed) * config.intensity;
+        (level > 0.0).then_some(level)
+    }
+}